@@ -0,0 +1,802 @@
+// 文档分块 - 入库和分块预览共用同一套分块器，保证预览结果与实际入库的分块一致。
+// 模型有 tokenizer.json 时按真实 token 数分块（远程模型首次使用时自动下载），没有分词器时才按字素分块；偏移均按字符计算
+
+use crate::local_embedding::LOCAL_PROVIDER_ID;
+use crate::types::{ChunkingStrategy, EmbeddingModel};
+use anyhow::{Result, anyhow};
+use tracing::warn;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+use unicode_segmentation::UnicodeSegmentation;
+
+// 按 token 分块时的默认块大小和重叠（token 数）
+pub const DEFAULT_CHUNK_TOKENS: usize = 512;
+pub const DEFAULT_CHUNK_OVERLAP_TOKENS: usize = 64;
+
+// 已加载的分词器，按 tokenizer.json 路径缓存
+static TOKENIZERS: Lazy<Mutex<HashMap<PathBuf, Arc<Tokenizer>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 远程模型的 tokenizer.json 从 Hugging Face 下载，可用 HF_ENDPOINT 环境变量指定镜像
+const DEFAULT_HF_ENDPOINT: &str = "https://huggingface.co";
+
+// 本次运行中下载分词器失败的模型，不再重复尝试
+static TOKENIZER_DOWNLOAD_FAILURES: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+const SENTENCE_TERMINATORS: &[&str] = &["。", "！", "？", "；", ".", "!", "?", ";", "\n"];
+
+// 分块结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextChunk {
+    pub index: usize,
+    pub text: String,
+    pub start_offset: usize, // 在原文中的字符偏移（左闭右开）
+    pub end_offset: usize,
+    pub token_count: usize,
+    #[serde(default)]
+    pub breadcrumb: Option<String>, // 所在的标题路径（仅 Markdown 分块）
+}
+
+pub trait Chunker: Send + Sync {
+    fn chunk(&self, content: &str) -> Vec<TextChunk>;
+
+    // 文本的 token 数，默认为估算值
+    fn count_tokens(&self, text: &str) -> usize {
+        estimate_tokens(text)
+    }
+
+    // 参数相同、块大小为 chunk_size 的分块器（Markdown 分块为标题路径预留空间时使用）
+    fn with_chunk_size(&self, chunk_size: usize) -> Box<dyn Chunker>;
+}
+
+// 按策略创建分块器，chunk_size / chunk_overlap 以字符计
+pub fn chunker_for(strategy: ChunkingStrategy, chunk_size: usize, chunk_overlap: usize) -> Box<dyn Chunker> {
+    let chunk_size = chunk_size.max(1);
+    // 重叠必须小于块大小，否则窗口无法前进
+    let chunk_overlap = chunk_overlap.min(chunk_size - 1);
+
+    match strategy {
+        ChunkingStrategy::Fixed => Box::new(FixedChunker { chunk_size, chunk_overlap }),
+        ChunkingStrategy::Sentence => Box::new(SentenceChunker { chunk_size, chunk_overlap }),
+        ChunkingStrategy::Markdown => Box::new(MarkdownChunker::new(
+            Box::new(SentenceChunker { chunk_size, chunk_overlap }),
+            chunk_size,
+            false,
+        )),
+    }
+}
+
+// 生成嵌入时的输入文本：有标题路径时加在分块文本前面，分块本身保存原文
+pub fn embedding_input(text: &str, breadcrumb: Option<&str>) -> String {
+    match breadcrumb {
+        Some(breadcrumb) => format!("{}\n\n{}", breadcrumb, text),
+        None => text.to_string(),
+    }
+}
+
+// 模型的 tokenizer.json 位置：本地模型在模型目录中，其他模型放在
+// <应用数据目录>/tokenizers/<model_id，"/" 替换为 "__">/tokenizer.json
+pub fn tokenizer_path(model: &EmbeddingModel, app_dir: &Path) -> PathBuf {
+    if model.provider == LOCAL_PROVIDER_ID {
+        return Path::new(&model.model_id).join("tokenizer.json");
+    }
+    app_dir.join("tokenizers").join(model.model_id.replace('/', "__")).join("tokenizer.json")
+}
+
+// 加载分词器（不截断、不补齐）
+pub fn load_tokenizer(path: &Path) -> Result<Arc<Tokenizer>> {
+    if let Some(tokenizer) = TOKENIZERS.lock().unwrap().get(path) {
+        return Ok(tokenizer.clone());
+    }
+
+    let mut tokenizer = Tokenizer::from_file(path)
+        .map_err(|e| anyhow!("加载分词器 {:?} 失败: {}", path, e))?;
+    tokenizer.with_padding(None);
+    tokenizer
+        .with_truncation(None)
+        .map_err(|e| anyhow!("设置截断参数失败: {}", e))?;
+
+    let tokenizer = Arc::new(tokenizer);
+    TOKENIZERS.lock().unwrap().insert(path.to_path_buf(), tokenizer.clone());
+    Ok(tokenizer)
+}
+
+// 安装远程模型的 tokenizer.json：确认能够加载后写入 tokenizer_path 对应的位置
+pub fn install_tokenizer(model: &EmbeddingModel, app_dir: &Path, bytes: &[u8]) -> Result<PathBuf> {
+    if model.provider == LOCAL_PROVIDER_ID {
+        return Err(anyhow!("本地模型 {} 使用模型目录中的 tokenizer.json", model.name));
+    }
+    Tokenizer::from_bytes(bytes).map_err(|e| anyhow!("无效的 tokenizer.json: {}", e))?;
+
+    let path = tokenizer_path(model, app_dir);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, bytes)?;
+    TOKENIZERS.lock().unwrap().remove(&path);
+    TOKENIZER_DOWNLOAD_FAILURES.lock().unwrap().remove(&model.model_id);
+    Ok(path)
+}
+
+// 从 Hugging Face 下载模型的 tokenizer.json（model_id 即仓库名，如 BAAI/bge-m3）
+pub async fn download_tokenizer(model: &EmbeddingModel, app_dir: &Path) -> Result<PathBuf> {
+    let endpoint = std::env::var("HF_ENDPOINT").unwrap_or_else(|_| DEFAULT_HF_ENDPOINT.to_string());
+    let url = format!("{}/{}/resolve/main/tokenizer.json", endpoint.trim_end_matches('/'), model.model_id);
+    let response = crate::embedding_http::HTTP_CLIENT
+        .get(&url)
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await?
+        .error_for_status()?;
+    let bytes = response.bytes().await?;
+    install_tokenizer(model, app_dir, &bytes)
+}
+
+// 模型可用的分词器：已安装时直接加载；远程模型未安装时尝试下载一次，仍不可用时返回 None
+pub async fn model_tokenizer(model: &EmbeddingModel, app_dir: &Path) -> Option<Arc<Tokenizer>> {
+    let path = tokenizer_path(model, app_dir);
+    if !path.is_file() {
+        let skip = model.provider == LOCAL_PROVIDER_ID
+            || TOKENIZER_DOWNLOAD_FAILURES.lock().unwrap().contains(&model.model_id);
+        if skip {
+            return None;
+        }
+        match download_tokenizer(model, app_dir).await {
+            Ok(path) => println!("📥 已下载模型 {} 的分词器: {:?}", model.name, path),
+            Err(e) => {
+                warn!("Failed to download tokenizer for {}: {}", model.model_id, e);
+                TOKENIZER_DOWNLOAD_FAILURES.lock().unwrap().insert(model.model_id.clone());
+                return None;
+            }
+        }
+    }
+
+    match load_tokenizer(&path) {
+        Ok(tokenizer) => Some(tokenizer),
+        Err(e) => {
+            warn!("Failed to load tokenizer for {}: {}", model.model_id, e);
+            None
+        }
+    }
+}
+
+// 粗略估算 token 数（约4字节一个 token）
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+// 固定长度窗口
+pub struct FixedChunker {
+    chunk_size: usize,
+    chunk_overlap: usize,
+}
+
+impl Chunker for FixedChunker {
+    fn chunk(&self, content: &str) -> Vec<TextChunk> {
+        split_windows(content, self.chunk_size, self.chunk_overlap, |_, _, end| end)
+    }
+
+    fn with_chunk_size(&self, chunk_size: usize) -> Box<dyn Chunker> {
+        chunker_for(ChunkingStrategy::Fixed, chunk_size, self.chunk_overlap)
+    }
+}
+
+// 窗口尽量在句子结束符之后截断；窗口后 2/3 内没有句子边界时按固定长度截断
+pub struct SentenceChunker {
+    chunk_size: usize,
+    chunk_overlap: usize,
+}
+
+impl Chunker for SentenceChunker {
+    fn chunk(&self, content: &str) -> Vec<TextChunk> {
+        let min_length = self.chunk_size / 3;
+        split_windows(content, self.chunk_size, self.chunk_overlap, |graphemes, start, end| {
+            (start + min_length + 1..=end)
+                .rev()
+                .find(|&i| SENTENCE_TERMINATORS.contains(&graphemes[i - 1]))
+                .unwrap_or(end)
+        })
+    }
+
+    fn with_chunk_size(&self, chunk_size: usize) -> Box<dyn Chunker> {
+        chunker_for(ChunkingStrategy::Sentence, chunk_size, self.chunk_overlap)
+    }
+}
+
+// 按模型分词器的 token 数分块，每块加上特殊 token 后不超过 max_tokens
+pub struct TokenChunker {
+    tokenizer: Arc<Tokenizer>,
+    strategy: ChunkingStrategy,
+    chunk_tokens: usize,
+    chunk_overlap: usize,
+    max_tokens: usize,
+}
+
+impl TokenChunker {
+    pub fn new(tokenizer: Arc<Tokenizer>, strategy: ChunkingStrategy, chunk_tokens: usize, chunk_overlap: usize, max_tokens: usize) -> Self {
+        // 模型输入会额外加上 [CLS]/[SEP] 等特殊 token
+        let special_tokens = tokenizer.encode("", true).map(|e| e.len()).unwrap_or(2);
+        let chunk_tokens = chunk_tokens.min(max_tokens.saturating_sub(special_tokens)).max(1);
+        Self {
+            tokenizer,
+            strategy,
+            chunk_tokens,
+            chunk_overlap: chunk_overlap.min(chunk_tokens - 1),
+            max_tokens,
+        }
+    }
+
+    pub fn chunk_tokens(&self) -> usize {
+        self.chunk_tokens
+    }
+
+    pub fn chunk_overlap(&self) -> usize {
+        self.chunk_overlap
+    }
+
+    fn model_input_tokens(&self, text: &str) -> usize {
+        self.tokenizer.encode(text, true).map(|e| e.len()).unwrap_or(usize::MAX)
+    }
+}
+
+impl Chunker for TokenChunker {
+    fn chunk(&self, content: &str) -> Vec<TextChunk> {
+        let encoding = match self.tokenizer.encode_char_offsets(content, false) {
+            Ok(encoding) => encoding,
+            Err(e) => {
+                println!("⚠️ 文档分词失败，改为按字符分块: {}", e);
+                return chunker_for(self.strategy, self.chunk_tokens, self.chunk_overlap).chunk(content);
+            }
+        };
+        let offsets = encoding.get_offsets();
+        let chars: Vec<char> = content.chars().collect();
+        let total = offsets.len();
+        let min_length = self.chunk_tokens / 3;
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < total {
+            let max_end = (start + self.chunk_tokens).min(total);
+            let mut end = max_end;
+            if self.strategy == ChunkingStrategy::Sentence && max_end < total {
+                end = (start + min_length + 1..=max_end)
+                    .rev()
+                    .find(|&i| ends_sentence(&chars, offsets, i))
+                    .unwrap_or(max_end);
+            }
+
+            // 分块文本重新分词后 token 数可能略有不同，超出模型上限时缩小窗口
+            let char_start = offsets[start].0;
+            let mut text = window_text(&chars, char_start, offsets[end - 1].1);
+            while end - start > 1 && self.model_input_tokens(&text) > self.max_tokens {
+                end -= 1;
+                text = window_text(&chars, char_start, offsets[end - 1].1);
+            }
+
+            if !text.trim().is_empty() {
+                chunks.push(TextChunk {
+                    index: chunks.len(),
+                    token_count: self.count_tokens(&text),
+                    start_offset: char_start,
+                    end_offset: char_start + text.chars().count(),
+                    text,
+                    breadcrumb: None,
+                });
+            }
+
+            start = if end >= total {
+                total
+            } else {
+                end.saturating_sub(self.chunk_overlap).max(start + 1)
+            };
+        }
+
+        chunks
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        self.tokenizer.encode(text, false).map(|e| e.len()).unwrap_or_else(|_| estimate_tokens(text))
+    }
+
+    fn with_chunk_size(&self, chunk_size: usize) -> Box<dyn Chunker> {
+        Box::new(TokenChunker::new(self.tokenizer.clone(), self.strategy, chunk_size, self.chunk_overlap, self.max_tokens))
+    }
+}
+
+// 调用方按字符指定块大小、而模型有分词器时使用：按 inner 切分，
+// 超出模型 max_tokens 的块再按 token 拆分，token 数按分词器计算
+pub struct TokenLimitedChunker {
+    inner: Box<dyn Chunker>,
+    tokens: TokenChunker,
+}
+
+impl TokenLimitedChunker {
+    pub fn new(inner: Box<dyn Chunker>, tokens: TokenChunker) -> Self {
+        Self { inner, tokens }
+    }
+}
+
+impl Chunker for TokenLimitedChunker {
+    fn chunk(&self, content: &str) -> Vec<TextChunk> {
+        let mut chunks: Vec<TextChunk> = Vec::new();
+        for piece in self.inner.chunk(content) {
+            let input = embedding_input(&piece.text, piece.breadcrumb.as_deref());
+            if self.tokens.model_input_tokens(&input) <= self.tokens.max_tokens {
+                chunks.push(TextChunk {
+                    index: chunks.len(),
+                    token_count: self.tokens.count_tokens(&piece.text),
+                    ..piece
+                });
+                continue;
+            }
+
+            // 标题路径同样计入模型输入，拆分时为其预留 token
+            let reserved = piece.breadcrumb.as_deref().map_or(0, |path| self.tokens.count_tokens(&embedding_input("", Some(path))));
+            let splitter = self.tokens.with_chunk_size(self.tokens.chunk_tokens.saturating_sub(reserved).max(1));
+            for sub in splitter.chunk(&piece.text) {
+                chunks.push(TextChunk {
+                    index: chunks.len(),
+                    start_offset: piece.start_offset + sub.start_offset,
+                    end_offset: piece.start_offset + sub.end_offset,
+                    breadcrumb: piece.breadcrumb.clone(),
+                    ..sub
+                });
+            }
+        }
+        chunks
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        self.tokens.count_tokens(text)
+    }
+
+    fn with_chunk_size(&self, chunk_size: usize) -> Box<dyn Chunker> {
+        let tokens = TokenChunker::new(
+            self.tokens.tokenizer.clone(), self.tokens.strategy, self.tokens.chunk_tokens, self.tokens.chunk_overlap, self.tokens.max_tokens,
+        );
+        Box::new(TokenLimitedChunker::new(self.inner.with_chunk_size(chunk_size), tokens))
+    }
+}
+
+fn window_text(chars: &[char], start: usize, end: usize) -> String {
+    chars[start..end.max(start)].iter().collect()
+}
+
+// 第 i 个 token 之前是否为句子结尾：前一个 token 以句子结束符结尾，或与下一个 token 之间有换行
+fn ends_sentence(chars: &[char], offsets: &[(usize, usize)], i: usize) -> bool {
+    let token = window_text(chars, offsets[i - 1].0, offsets[i - 1].1);
+    let gap_end = offsets.get(i).map_or(chars.len(), |next| next.0);
+    SENTENCE_TERMINATORS.iter().any(|t| token.ends_with(t))
+        || chars[offsets[i - 1].1.min(gap_end)..gap_end].contains(&'\n')
+}
+
+// Markdown 结构化分块：遇到标题开始新块，代码块和表格不在中间截断，
+// 同一标题下的段落合并到块大小上限；单个块超过上限时才交给 inner 拆分
+pub struct MarkdownChunker {
+    inner: Box<dyn Chunker>,
+    limit: usize,
+    measure_tokens: bool, // limit 按 token 数（inner.count_tokens）还是按字素计
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BlockKind {
+    Heading(usize, String), // 级别、标题文本
+    Code,
+    Table,
+    Text,
+}
+
+// 文档中的一个结构块，偏移按字符计（左闭右开，包含行尾换行）
+#[derive(Debug)]
+struct Block {
+    kind: BlockKind,
+    start: usize,
+    end: usize,
+}
+
+impl MarkdownChunker {
+    pub fn new(inner: Box<dyn Chunker>, limit: usize, measure_tokens: bool) -> Self {
+        Self { inner, limit: limit.max(1), measure_tokens }
+    }
+
+    // 按嵌入时的实际输入（含标题路径）计算大小
+    fn size(&self, text: &str, breadcrumb: Option<&str>) -> usize {
+        let input = embedding_input(text, breadcrumb);
+        if self.measure_tokens {
+            self.inner.count_tokens(&input)
+        } else {
+            input.graphemes(true).count()
+        }
+    }
+
+    fn push_chunk(&self, chunks: &mut Vec<TextChunk>, chars: &[char], start: usize, end: usize, breadcrumb: &Option<String>) {
+        // 去掉块末尾的空行
+        let mut end = end;
+        while end > start && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+        if end == start {
+            return;
+        }
+        let text = window_text(chars, start, end);
+        chunks.push(TextChunk {
+            index: chunks.len(),
+            token_count: self.inner.count_tokens(&text),
+            text,
+            start_offset: start,
+            end_offset: end,
+            breadcrumb: breadcrumb.clone(),
+        });
+    }
+
+    // 超出上限的块：代码块和表格按行拆分，段落交给 inner 拆分（块大小扣除标题路径占用的部分），偏移换算回原文
+    fn push_split(&self, chunks: &mut Vec<TextChunk>, chars: &[char], start: usize, end: usize, kind: &BlockKind, breadcrumb: &Option<String>) {
+        if matches!(kind, BlockKind::Code | BlockKind::Table) {
+            self.push_lines(chunks, chars, start, end, breadcrumb);
+            return;
+        }
+
+        let reserved = breadcrumb.as_deref().map_or(0, |path| self.size("", Some(path)));
+        let inner = self.inner.with_chunk_size(self.limit.saturating_sub(reserved).max(1));
+        for piece in inner.chunk(&window_text(chars, start, end)) {
+            chunks.push(TextChunk {
+                index: chunks.len(),
+                start_offset: start + piece.start_offset,
+                end_offset: start + piece.end_offset,
+                breadcrumb: breadcrumb.clone(),
+                ..piece
+            });
+        }
+    }
+
+    // 按行合并到上限，只在行边界拆分；单独一行就超出上限时才在行内截断
+    fn push_lines(&self, chunks: &mut Vec<TextChunk>, chars: &[char], start: usize, end: usize, breadcrumb: &Option<String>) {
+        let fits = |from: usize, to: usize| self.size(&window_text(chars, from, to), breadcrumb.as_deref()) <= self.limit;
+        let mut piece_start = start;
+        let mut piece_end = start;
+
+        let mut line_start = start;
+        while line_start < end {
+            let line_end = (line_start..end).find(|&i| chars[i] == '\n').map_or(end, |i| i + 1);
+            if piece_end > piece_start && !fits(piece_start, line_end) {
+                self.push_chunk(chunks, chars, piece_start, piece_end, breadcrumb);
+                piece_start = piece_end;
+            }
+            while piece_start < line_end && !fits(piece_start, line_end) {
+                // 二分查找能放下的最长前缀，至少保留一个字符
+                let (mut low, mut high) = (piece_start + 1, line_end);
+                while low < high {
+                    let mid = (low + high).div_ceil(2);
+                    if fits(piece_start, mid) { low = mid } else { high = mid - 1 }
+                }
+                self.push_chunk(chunks, chars, piece_start, low, breadcrumb);
+                piece_start = low;
+            }
+            piece_end = line_end;
+            line_start = line_end;
+        }
+
+        self.push_chunk(chunks, chars, piece_start, piece_end, breadcrumb);
+    }
+}
+
+impl Chunker for MarkdownChunker {
+    fn chunk(&self, content: &str) -> Vec<TextChunk> {
+        let chars: Vec<char> = content.chars().collect();
+        let mut headings: Vec<(usize, String)> = Vec::new();
+        let mut chunks = Vec::new();
+        // 当前块的起点，以及是否已有正文（只有标题时遇到下一个标题不单独成块）
+        let mut current: Option<usize> = None;
+        let mut has_body = false;
+        let mut current_end = 0;
+
+        for block in markdown_blocks(content) {
+            if let BlockKind::Heading(level, title) = &block.kind {
+                if has_body {
+                    self.push_chunk(&mut chunks, &chars, current.take().unwrap(), current_end, &breadcrumb(&headings));
+                    has_body = false;
+                }
+                while headings.last().is_some_and(|(l, _)| l >= level) {
+                    headings.pop();
+                }
+                headings.push((*level, title.clone()));
+                current.get_or_insert(block.start);
+                current_end = block.end;
+                continue;
+            }
+
+            let path = breadcrumb(&headings);
+            if let Some(start) = current {
+                if has_body && self.size(&window_text(&chars, start, block.end), path.as_deref()) > self.limit {
+                    self.push_chunk(&mut chunks, &chars, start, current_end, &path);
+                    current = None;
+                }
+            }
+
+            let start = current.unwrap_or(block.start);
+            if self.size(&window_text(&chars, start, block.end), path.as_deref()) > self.limit {
+                self.push_split(&mut chunks, &chars, start, block.end, &block.kind, &path);
+                current = None;
+                has_body = false;
+            } else {
+                current = Some(start);
+                current_end = block.end;
+                has_body = true;
+            }
+        }
+
+        if let Some(start) = current {
+            self.push_chunk(&mut chunks, &chars, start, current_end, &breadcrumb(&headings));
+        }
+
+        chunks
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        self.inner.count_tokens(text)
+    }
+
+    fn with_chunk_size(&self, chunk_size: usize) -> Box<dyn Chunker> {
+        Box::new(MarkdownChunker::new(self.inner.with_chunk_size(chunk_size), chunk_size, self.measure_tokens))
+    }
+}
+
+fn breadcrumb(headings: &[(usize, String)]) -> Option<String> {
+    if headings.is_empty() {
+        return None;
+    }
+    Some(headings.iter().map(|(_, title)| title.as_str()).collect::<Vec<_>>().join(" > "))
+}
+
+// ATX 标题：最多 3 个空格缩进，1-6 个 #，后面是空白或行尾
+fn parse_heading(line: &str) -> Option<(usize, String)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let rest = &line[indent..];
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    if indent > 3 || !(1..=6).contains(&level) {
+        return None;
+    }
+    let title = &rest[level..];
+    if !title.is_empty() && !title.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some((level, title.trim().trim_end_matches('#').trim_end().to_string()))
+}
+
+// 代码围栏：至少 3 个 ` 或 ~，返回围栏字符和长度
+fn parse_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start();
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = trimmed.chars().take_while(|c| *c == marker).count();
+    (length >= 3 && line.len() - trimmed.len() <= 3).then_some((marker, length))
+}
+
+// 把 Markdown 拆成标题、代码块、表格和段落；空行分隔段落，未闭合的代码块延续到文末
+fn markdown_blocks(content: &str) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut current: Option<Block> = None;
+    let mut fence: Option<(char, usize)> = None;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.chars().count();
+
+        if let Some((marker, length)) = fence {
+            if let Some(block) = current.as_mut() {
+                block.end = offset;
+            }
+            let closing = parse_fence(line)
+                .is_some_and(|(m, l)| m == marker && l >= length && line.trim().chars().all(|c| c == marker));
+            if closing {
+                blocks.extend(current.take());
+                fence = None;
+            }
+            continue;
+        }
+
+        if let Some(opening) = parse_fence(line) {
+            blocks.extend(current.take());
+            fence = Some(opening);
+            current = Some(Block { kind: BlockKind::Code, start, end: offset });
+            continue;
+        }
+
+        if let Some((level, title)) = parse_heading(line.trim_end()) {
+            blocks.extend(current.take());
+            blocks.push(Block { kind: BlockKind::Heading(level, title), start, end: offset });
+            continue;
+        }
+
+        if line.trim().is_empty() {
+            blocks.extend(current.take());
+            continue;
+        }
+
+        let kind = if line.trim_start().starts_with('|') { BlockKind::Table } else { BlockKind::Text };
+        match current.as_mut() {
+            Some(block) if block.kind == kind => block.end = offset,
+            _ => {
+                blocks.extend(current.take());
+                current = Some(Block { kind, start, end: offset });
+            }
+        }
+    }
+
+    blocks.extend(current);
+    blocks
+}
+
+// 滑动窗口分块。choose_end(字素, 窗口起点, 窗口最大终点) 返回实际终点，
+// 只在窗口未到达文末时调用；下一个窗口从终点回退 overlap 个字素开始
+fn split_windows(
+    content: &str,
+    chunk_size: usize,
+    chunk_overlap: usize,
+    choose_end: impl Fn(&[&str], usize, usize) -> usize,
+) -> Vec<TextChunk> {
+    let graphemes: Vec<&str> = content.graphemes(true).collect();
+    let total = graphemes.len();
+
+    // 每个字素之前的字符数
+    let mut char_offsets = Vec::with_capacity(total + 1);
+    char_offsets.push(0);
+    for grapheme in &graphemes {
+        char_offsets.push(char_offsets.last().unwrap() + grapheme.chars().count());
+    }
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < total {
+        let max_end = (start + chunk_size).min(total);
+        let end = if max_end < total { choose_end(&graphemes, start, max_end) } else { max_end };
+
+        let text = graphemes[start..end].concat();
+        if !text.trim().is_empty() {
+            chunks.push(TextChunk {
+                index: chunks.len(),
+                token_count: estimate_tokens(&text),
+                text,
+                start_offset: char_offsets[start],
+                end_offset: char_offsets[end],
+                breadcrumb: None,
+            });
+        }
+
+        start = if end >= total {
+            total
+        } else {
+            end.saturating_sub(chunk_overlap).max(start + 1)
+        };
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn char_slice(content: &str, chunk: &TextChunk) -> String {
+        content.chars().skip(chunk.start_offset).take(chunk.end_offset - chunk.start_offset).collect()
+    }
+
+    fn word_tokenizer() -> Arc<Tokenizer> {
+        use tokenizers::models::wordlevel::WordLevel;
+        use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+        let vocab = ["[UNK]", "one", "two", "three", "four", "five", "."]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect();
+        let model = WordLevel::builder().vocab(vocab).unk_token("[UNK]".to_string()).build().unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+        Arc::new(tokenizer)
+    }
+
+    #[test]
+    fn token_chunker_respects_token_budget() {
+        let content = "one two three. four five one two. three four five";
+        let chunker = TokenChunker::new(word_tokenizer(), ChunkingStrategy::Sentence, 100, 1, 6);
+        let chunks = chunker.chunk(content);
+
+        assert_eq!(chunks[0].text, "one two three.");
+        for chunk in &chunks {
+            assert!(chunk.token_count <= 6);
+            assert_eq!(chunk.token_count, chunker.count_tokens(&chunk.text));
+            assert_eq!(char_slice(content, chunk), chunk.text);
+        }
+        assert!(chunks.last().unwrap().text.ends_with("five"));
+    }
+
+    #[test]
+    fn markdown_chunker_follows_headings_and_keeps_code_intact() {
+        let content = "# Guide\n\nIntro text.\n\n## Install\n\n### Linux\n\nRun this:\n\n```sh\ncargo build\n\ncargo run\n```\n\n| os | ok |\n|----|----|\n| linux | yes |\n\n## Usage\n\nStart the app.\n";
+        let chunks = chunker_for(ChunkingStrategy::Markdown, 200, 0).chunk(content);
+
+        let breadcrumbs: Vec<Option<&str>> = chunks.iter().map(|c| c.breadcrumb.as_deref()).collect();
+        assert_eq!(breadcrumbs, vec![Some("Guide"), Some("Guide > Install > Linux"), Some("Guide > Usage")]);
+        assert!(chunks[1].text.starts_with("## Install"));
+        assert!(chunks[1].text.contains("cargo build\n\ncargo run\n```"));
+        assert!(chunks[1].text.ends_with("| linux | yes |"));
+        for chunk in &chunks {
+            assert_eq!(char_slice(content, chunk), chunk.text);
+        }
+
+        // 块大小不足以容纳整节时，代码块单独成块而不被截断
+        let chunks = chunker_for(ChunkingStrategy::Markdown, 60, 0).chunk(content);
+        assert!(chunks.iter().any(|c| c.text == "```sh\ncargo build\n\ncargo run\n```"));
+        assert!(chunks.iter().all(|c| c.breadcrumb.is_some()));
+    }
+
+    #[test]
+    fn markdown_chunker_splits_oversized_code_and_tables_on_lines() {
+        let code: String = (0..30).map(|i| format!("let value_{} = compute({});\n", i, i)).collect();
+        let table: String = (0..30).map(|i| format!("| row {} | value {} |\n", i, i)).collect();
+        let content = format!("# Guide\n\n## Code\n\n```rust\n{}```\n\n## Table\n\n| name | value |\n|---|---|\n{}", code, table);
+        let limit = 120;
+        let chunks = chunker_for(ChunkingStrategy::Markdown, limit, 10).chunk(&content);
+
+        for chunk in &chunks {
+            assert_eq!(char_slice(&content, chunk), chunk.text);
+            // 加上标题路径后仍不超过上限
+            assert!(embedding_input(&chunk.text, chunk.breadcrumb.as_deref()).graphemes(true).count() <= limit);
+        }
+
+        // 代码块按整行拆分，没有行被截断
+        let code_chunks: Vec<&TextChunk> = chunks.iter().filter(|c| c.breadcrumb.as_deref() == Some("Guide > Code")).collect();
+        assert!(code_chunks.len() > 1);
+        let code_lines: Vec<&str> = code_chunks.iter().flat_map(|c| c.text.lines()).collect();
+        assert!(code_lines.iter().filter(|line| line.starts_with("let ")).all(|line| code.contains(&format!("{}\n", line))));
+        assert_eq!(code_lines.iter().filter(|line| line.starts_with("let ")).count(), 30);
+
+        // 表格同样按行拆分
+        let table_chunks: Vec<&TextChunk> = chunks.iter().filter(|c| c.breadcrumb.as_deref() == Some("Guide > Table")).collect();
+        assert!(table_chunks.len() > 1);
+        for chunk in &table_chunks {
+            assert!(chunk.text.lines().all(|line| (line.starts_with('|') && line.ends_with('|')) || line.starts_with('#') || line.is_empty()));
+        }
+        assert_eq!(table_chunks.iter().flat_map(|c| c.text.lines()).filter(|line| line.starts_with("| row")).count(), 30);
+    }
+
+    #[test]
+    fn markdown_chunker_reserves_room_for_breadcrumb() {
+        let content = format!("# 很长的一级标题\n\n## 同样很长的二级标题\n\n{}", "这是一句比较长的正文内容。".repeat(20));
+        let limit = 60;
+        let chunks = chunker_for(ChunkingStrategy::Markdown, limit, 5).chunk(&content);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert_eq!(chunk.breadcrumb.as_deref(), Some("很长的一级标题 > 同样很长的二级标题"));
+            assert!(embedding_input(&chunk.text, chunk.breadcrumb.as_deref()).graphemes(true).count() <= limit);
+        }
+    }
+
+    #[test]
+    fn sentence_chunker_breaks_at_boundaries() {
+        let content = "第一句话比较长一些。第二句。第三句话也很长很长。";
+        let chunks = chunker_for(ChunkingStrategy::Sentence, 12, 2).chunk(content);
+
+        assert_eq!(chunks[0].text, "第一句话比较长一些。");
+        for chunk in &chunks {
+            assert_eq!(char_slice(content, chunk), chunk.text);
+        }
+        assert_eq!(chunks.last().unwrap().end_offset, content.chars().count());
+    }
+
+    #[test]
+    fn fixed_chunker_handles_multibyte_text_and_large_overlap() {
+        let content = "😀中文ab".repeat(5);
+        let chunks = chunker_for(ChunkingStrategy::Fixed, 4, 10).chunk(&content);
+
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 4));
+        for chunk in &chunks {
+            assert_eq!(char_slice(&content, chunk), chunk.text);
+        }
+    }
+}
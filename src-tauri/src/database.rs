@@ -25,11 +25,294 @@ pub static DB_POOL: Lazy<Arc<DatabaseManager>> = Lazy::new(|| {
     panic!("DatabaseManager must be initialized manually")
 });
 
+// knowledge_vectors 表的维度，其他维度的向量存放在 knowledge_vectors_<维度> 表中
+pub const DEFAULT_VECTOR_DIMENSIONS: i32 = 1024;
+
+// 指定维度和距离度量的向量表名（L2 沿用原有表名，余弦距离的表带 _cosine 后缀）
+pub fn vector_table_name(dimensions: i32, metric: DistanceMetric) -> String {
+    let table = if dimensions == DEFAULT_VECTOR_DIMENSIONS {
+        "knowledge_vectors".to_string()
+    } else {
+        format!("knowledge_vectors_{}", dimensions)
+    };
+
+    match metric.vec0_metric() {
+        "l2" => table,
+        vec0_metric => format!("{}_{}", table, vec0_metric),
+    }
+}
+
+// 集合使用的向量表
+#[derive(Debug, Clone)]
+pub struct VectorTable {
+    pub name: String,
+    pub dimensions: i32,
+    pub metric: DistanceMetric,
+}
+
+// 搜索过滤条件的绑定参数
+enum FilterArg {
+    Text(String),
+    Int(i64),
+}
+
+// 把搜索过滤条件转换为限定候选分块的子查询条件（kd 为 knowledge_documents 别名）
+fn search_filter_sql(filters: &SearchFilters) -> (String, Vec<FilterArg>) {
+    let mut conditions = Vec::new();
+    let mut args = Vec::new();
+
+    let mut push_in = |column: &str, values: &[String], conditions: &mut Vec<String>| {
+        conditions.push(format!("{} IN ({})", column, vec!["?"; values.len()].join(", ")));
+        args.extend(values.iter().cloned().map(FilterArg::Text));
+    };
+    if let Some(ids) = filters.document_ids.as_ref().filter(|ids| !ids.is_empty()) {
+        push_in("kd.id", ids, &mut conditions);
+    }
+    if let Some(types) = filters.mime_types.as_ref().filter(|types| !types.is_empty()) {
+        push_in("kd.mime_type", types, &mut conditions);
+    }
+
+    if let Some(glob) = &filters.file_name_glob {
+        conditions.push("kd.file_name GLOB ?".to_string());
+        args.push(FilterArg::Text(glob.clone()));
+    }
+    if let Some(metadata) = &filters.metadata {
+        for (key, value) in metadata {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM json_each(CASE WHEN json_valid(kd.metadata) THEN kd.metadata ELSE '{}' END) m
+                         WHERE m.key = ? AND CAST(m.value AS TEXT) = ?)".to_string()
+            );
+            args.push(FilterArg::Text(key.clone()));
+            args.push(FilterArg::Text(value.clone()));
+        }
+    }
+    if let Some(after) = filters.created_after {
+        conditions.push("kd.created_at >= ?".to_string());
+        args.push(FilterArg::Int(after));
+    }
+    if let Some(before) = filters.created_before {
+        conditions.push("kd.created_at <= ?".to_string());
+        args.push(FilterArg::Int(before));
+    }
+
+    (conditions.join(" AND "), args)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'     // 平假名、片假名
+        | '\u{3400}'..='\u{4DBF}'   // CJK 扩展 A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK 统一汉字
+        | '\u{AC00}'..='\u{D7AF}'   // 韩文音节
+        | '\u{F900}'..='\u{FAFF}'   // CJK 兼容汉字
+        | '\u{20000}'..='\u{2FFFF}' // CJK 扩展 B 及以后
+    )
+}
+
+// 关键词检索的查询词：按空白和全角标点拆词，连续的中日韩文字按重叠的三字组拆分
+// （中文查询通常不带空格，整句作为一个短语几乎不可能命中）。少于2个字符的词被忽略。
+// 搜索结果高亮使用同一组查询词，高亮与关键词检索的命中保持一致
+pub fn keyword_terms(text: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    let mut push = |term: String| {
+        if term.chars().count() >= 2 && !terms.contains(&term) {
+            terms.push(term);
+        }
+    };
+
+    for word in text.split(|c: char| c.is_whitespace() || (!c.is_ascii() && !c.is_alphanumeric())) {
+        let chars: Vec<char> = word.chars().collect();
+        let mut start = 0;
+        while start < chars.len() {
+            let cjk = is_cjk(chars[start]);
+            let end = (start..chars.len()).find(|&i| is_cjk(chars[i]) != cjk).unwrap_or(chars.len());
+            let run = &chars[start..end];
+            if cjk && run.len() > 3 {
+                for window in run.windows(3) {
+                    push(window.iter().collect());
+                }
+            } else {
+                push(run.iter().collect());
+            }
+            start = end;
+        }
+    }
+
+    // trigram 索引无法匹配少于3个字符的词：有更长的词时丢弃短词，否则全部交给 LIKE 匹配
+    if terms.iter().any(|term| term.chars().count() >= 3) {
+        terms.retain(|term| term.chars().count() >= 3);
+    }
+    terms
+}
+
+// 把查询词转换为 FTS5 查询：每个词作为短语匹配，词之间为 OR。
+// 查询词少于3个字符时 trigram 无法匹配，返回 None，由调用方改用 LIKE
+fn fts_match_query(terms: &[String]) -> Option<String> {
+    if terms.is_empty() || terms.iter().any(|term| term.chars().count() < 3) {
+        return None;
+    }
+
+    let phrases: Vec<String> = terms
+        .iter()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    Some(phrases.join(" OR "))
+}
+
+// 转义 LIKE 模式中的通配符，配合 ESCAPE '\' 使用
+pub fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn chunk_from_row(row: &sqlx::sqlite::SqliteRow) -> KnowledgeChunk {
+    KnowledgeChunk {
+        id: row.get::<i64, _>("id"),
+        document_id: row.get("document_id"),
+        chunk_index: row.get("chunk_index"),
+        chunk_text: row.get("chunk_text"),
+        token_count: row.get("token_count"),
+        start_offset: row.get("start_offset"),
+        end_offset: row.get("end_offset"),
+        metadata: row.get("metadata"),
+        created_at: chrono::DateTime::from_timestamp(row.get::<i64, _>("created_at"), 0)
+            .unwrap_or_default(),
+    }
+}
+
+// 向量与 BLOB 互转（f32 原生字节序，与 sqlite-vec 的向量格式一致）
+fn embedding_to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.as_bytes().to_vec()
+}
+
+fn embedding_from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+// 向量检索的缓存键：对查询向量的完整字节和所有影响结果的参数取 SHA-256
+fn search_cache_key(
+    query_embedding: &[f32],
+    collection_id: &str,
+    embedding_model: &str,
+    vector_table: &VectorTable,
+    limit: usize,
+    threshold: f32,
+    filters: Option<&SearchFilters>,
+) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for part in [collection_id, embedding_model, vector_table.name.as_str()] {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    hasher.update((limit as u64).to_le_bytes());
+    hasher.update(threshold.to_bits().to_le_bytes());
+    hasher.update(filters.map(serde_json::to_string).transpose()?.unwrap_or_default().as_bytes());
+    hasher.update([0u8]);
+    hasher.update(query_embedding.as_bytes());
+
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+struct CachedSearch {
+    collection_id: String,
+    results: Vec<SearchResult>,
+    cached_at: std::time::Instant,
+}
+
+// 向量检索结果缓存：条目超过 TTL 视为未命中，集合数据变更时只清除该集合的条目
+struct QueryCache {
+    entries: LruCache<String, CachedSearch>,
+    ttl: std::time::Duration,
+    hits: u64,
+    misses: u64,
+    expired: u64,
+    invalidated: u64,
+}
+
+impl QueryCache {
+    fn new(capacity: usize, ttl_seconds: u64) -> Self {
+        Self {
+            entries: LruCache::new(std::num::NonZeroUsize::new(capacity).unwrap()),
+            ttl: std::time::Duration::from_secs(ttl_seconds),
+            hits: 0,
+            misses: 0,
+            expired: 0,
+            invalidated: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Vec<SearchResult>> {
+        let fresh = match self.entries.get(key) {
+            Some(entry) => entry.cached_at.elapsed() < self.ttl,
+            None => {
+                self.misses += 1;
+                return None;
+            }
+        };
+
+        if fresh {
+            self.hits += 1;
+            self.entries.get(key).map(|entry| entry.results.clone())
+        } else {
+            self.entries.pop(key);
+            self.expired += 1;
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn put(&mut self, key: String, collection_id: &str, results: Vec<SearchResult>) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.entries.put(key, CachedSearch {
+            collection_id: collection_id.to_string(),
+            results,
+            cached_at: std::time::Instant::now(),
+        });
+    }
+
+    fn invalidate_collection(&mut self, collection_id: &str) -> usize {
+        let keys: Vec<String> = self.entries.iter()
+            .filter(|(_, entry)| entry.collection_id == collection_id)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &keys {
+            self.entries.pop(key);
+        }
+        self.invalidated += keys.len() as u64;
+        keys.len()
+    }
+
+    fn clear(&mut self) -> usize {
+        let count = self.entries.len();
+        self.entries.clear();
+        self.invalidated += count as u64;
+        count
+    }
+
+    fn stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            entries: self.entries.len(),
+            capacity: self.entries.cap().get(),
+            hits: self.hits,
+            misses: self.misses,
+            expired: self.expired,
+            invalidated: self.invalidated,
+            ttl_seconds: self.ttl.as_secs(),
+        }
+    }
+}
+
 // 数据库管理器
 pub struct DatabaseManager {
     main_pool: Pool<Sqlite>,
     knowledge_pool: Pool<Sqlite>,
-    query_cache: Arc<std::sync::Mutex<LruCache<String, Vec<SearchResult>>>>,
+    query_cache: Arc<std::sync::Mutex<QueryCache>>,
+    app_dir: std::path::PathBuf,
 }
 
 impl DatabaseManager {
@@ -145,6 +428,28 @@ impl DatabaseManager {
             sqlx::query(query).execute(&knowledge_pool).await?;
         }
 
+        Self::open(main_pool, knowledge_pool, app_dir).await
+    }
+
+    // 测试用的内存数据库（单连接，保证所有查询看到同一个库）
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self> {
+        let _ = &*SQLITE_VEC_INIT;
+        let memory_pool = || SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:");
+        let main_pool = memory_pool().await?;
+        let knowledge_pool = memory_pool().await?;
+        sqlx::query("PRAGMA foreign_keys=ON").execute(&knowledge_pool).await?;
+        // 每个测试库使用独立的应用数据目录，避免测试之间共享下载的分词器等文件
+        let app_dir = std::env::temp_dir().join(format!("knowledge-test-{}", uuid::Uuid::new_v4()));
+        Self::open(main_pool, knowledge_pool, app_dir).await
+    }
+
+    // 初始化表结构并创建管理器
+    async fn open(main_pool: Pool<Sqlite>, knowledge_pool: Pool<Sqlite>, app_dir: std::path::PathBuf) -> Result<Self> {
         // 初始化数据库结构
         if let Err(e) = Self::initialize_databases(&main_pool, &knowledge_pool).await {
             error!("Failed to initialize database schema: {}", e);
@@ -172,10 +477,18 @@ impl DatabaseManager {
             }
         }
 
+        // 查询缓存的过期时间取自 system_config 的 cache_ttl（秒）
+        let cache_ttl = sqlx::query("SELECT value FROM system_config WHERE key = 'cache_ttl'")
+            .fetch_optional(&knowledge_pool)
+            .await?
+            .and_then(|row| row.get::<String, _>(0).parse::<u64>().ok())
+            .unwrap_or(3600);
+
         Ok(Self {
             main_pool,
             knowledge_pool,
-            query_cache: Arc::new(std::sync::Mutex::new(LruCache::new(std::num::NonZeroUsize::new(1000).unwrap()))),
+            query_cache: Arc::new(std::sync::Mutex::new(QueryCache::new(1000, cache_ttl))),
+            app_dir,
         })
     }
 
@@ -232,7 +545,7 @@ impl DatabaseManager {
         // 创建新的向量表（使用正确的sqlite-vec语法）
         println!("🏗️ 创建新的向量表（使用正确的sqlite-vec语法）...");
         sqlx::query(
-            "CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_vectors USING vec0(embedding FLOAT[1024])"
+            "CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_vectors USING vec0(collection_id TEXT PARTITION KEY, embedding FLOAT[1024])"
         )
         .execute(pool)
         .await?;
@@ -346,7 +659,8 @@ impl DatabaseManager {
                 enabled INTEGER DEFAULT 1,
                 max_tokens INTEGER DEFAULT 8192,
                 created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
+                updated_at INTEGER NOT NULL,
+                provider TEXT NOT NULL DEFAULT 'siliconflow'
             )"
         ];
 
@@ -358,6 +672,9 @@ impl DatabaseManager {
             }
         }
 
+        // 迁移：旧版 embedding_models 表缺少 provider 列
+        Self::migrate_embedding_model_provider(main_pool).await?;
+
         // 创建索引
         let index_queries = vec![
             "CREATE INDEX IF NOT EXISTS idx_roles_sort_order ON roles(sort_order)",
@@ -478,7 +795,9 @@ impl DatabaseManager {
                 embedding_model TEXT NOT NULL DEFAULT 'bge-m3',
                 vector_dimensions INTEGER NOT NULL DEFAULT 1024,
                 created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
+                updated_at INTEGER NOT NULL,
+                distance_metric TEXT NOT NULL DEFAULT 'cosine',
+                chunking_strategy TEXT NOT NULL DEFAULT 'fixed'
             )",
             "CREATE TABLE IF NOT EXISTS knowledge_documents (
                 id TEXT PRIMARY KEY,
@@ -501,9 +820,12 @@ impl DatabaseManager {
                 chunk_text TEXT NOT NULL,
                 token_count INTEGER DEFAULT 0,
                 created_at INTEGER NOT NULL,
+                start_offset INTEGER,
+                end_offset INTEGER,
+                metadata TEXT,
                 FOREIGN KEY (document_id) REFERENCES knowledge_documents(id) ON DELETE CASCADE
             )",
-            "CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_vectors USING vec0(embedding FLOAT[1024])",
+            "CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_vectors USING vec0(collection_id TEXT PARTITION KEY, embedding FLOAT[1024])",
             "CREATE TABLE IF NOT EXISTS system_config (
                 key TEXT PRIMARY KEY,
                 value TEXT NOT NULL,
@@ -544,7 +866,32 @@ impl DatabaseManager {
                 results TEXT,
                 created_at INTEGER NOT NULL,
                 expires_at INTEGER NOT NULL
-            )"
+            )",
+            "CREATE TABLE IF NOT EXISTS embedding_cache (
+                model_id TEXT NOT NULL,
+                text_hash TEXT NOT NULL,
+                dimensions INTEGER NOT NULL,
+                embedding BLOB NOT NULL,
+                created_at INTEGER NOT NULL,
+                split_offsets TEXT,
+                PRIMARY KEY (model_id, text_hash)
+            )",
+            "CREATE TABLE IF NOT EXISTS embedding_usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                provider TEXT NOT NULL,
+                model_id TEXT NOT NULL,
+                operation TEXT NOT NULL,
+                collection_id TEXT,
+                document_id TEXT,
+                text_count INTEGER NOT NULL DEFAULT 0,
+                prompt_tokens INTEGER NOT NULL DEFAULT 0,
+                total_tokens INTEGER NOT NULL DEFAULT 0,
+                estimated INTEGER NOT NULL DEFAULT 0,
+                latency_ms INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL
+            )",
+            "CREATE INDEX IF NOT EXISTS idx_embedding_usage_created_at ON embedding_usage(created_at)",
+            "CREATE INDEX IF NOT EXISTS idx_embedding_usage_collection_id ON embedding_usage(collection_id)"
         ];
 
         for query in knowledge_queries {
@@ -591,7 +938,7 @@ impl DatabaseManager {
 
         // 插入默认嵌入模型配置
         let default_models = vec![
-            ("bge-m3", "BGE-M3", "bge-m3", 384, "universal", 512),
+            ("bge-m3", "BGE-M3", "bge-m3", 1024, "universal", 512),
             ("bge-large-zh", "BGE-Large-ZH", "BAAI/bge-large-zh-v1.5", 1024, "zh", 512),
             ("bge-large-en", "BGE-Large-EN", "BAAI/bge-large-en-v1.5", 1024, "en", 512),
         ];
@@ -614,39 +961,245 @@ impl DatabaseManager {
             }
         }
 
-        // 迁移：更新现有集合的vector_dimensions从384到1024
+        // 旧版本的种子数据把 bge-m3 写成了384维
+        sqlx::query("UPDATE embedding_models SET dimensions = 1024 WHERE id = 'bge-m3' AND dimensions = 384")
+            .execute(knowledge_pool)
+            .await?;
+
+        // 迁移：旧版集合缺少 distance_metric 列，已有集合的向量表均为 L2 距离
+        if !Self::column_exists(knowledge_pool, "knowledge_collections", "distance_metric").await? {
+            info!("Adding distance_metric column to knowledge_collections...");
+            sqlx::query("ALTER TABLE knowledge_collections ADD COLUMN distance_metric TEXT NOT NULL DEFAULT 'cosine'")
+                .execute(knowledge_pool)
+                .await?;
+            sqlx::query("UPDATE knowledge_collections SET distance_metric = 'l2'")
+                .execute(knowledge_pool)
+                .await?;
+        }
+
+        if !Self::column_exists(knowledge_pool, "knowledge_collections", "chunking_strategy").await? {
+            sqlx::query("ALTER TABLE knowledge_collections ADD COLUMN chunking_strategy TEXT NOT NULL DEFAULT 'fixed'")
+                .execute(knowledge_pool)
+                .await?;
+        }
+
+        // 迁移：为每个集合准备对应维度的向量表
         Self::migrate_collection_dimensions(knowledge_pool).await?;
 
+        // 迁移：向量表增加 collection_id 分区键，以支持按集合的 KNN 查询
+        Self::migrate_vector_partitions(knowledge_pool).await?;
+
+        // 迁移：确保每个集合的维度和距离度量都有对应的向量表（查询时只按表名查找，不再建表）
+        let vector_tables = sqlx::query("SELECT DISTINCT vector_dimensions, distance_metric FROM knowledge_collections WHERE vector_dimensions > 0")
+            .fetch_all(knowledge_pool)
+            .await?;
+        for row in vector_tables {
+            let metric = DistanceMetric::parse(row.get(1)).unwrap_or_default();
+            Self::create_vector_table(knowledge_pool, row.get(0), metric).await?;
+        }
+
+        // 迁移：分块在文档中的字符偏移
+        for column in ["start_offset", "end_offset"] {
+            if !Self::column_exists(knowledge_pool, "knowledge_chunks", column).await? {
+                sqlx::query(&format!("ALTER TABLE knowledge_chunks ADD COLUMN {} INTEGER", column))
+                    .execute(knowledge_pool)
+                    .await?;
+            }
+        }
+
+        // 迁移：嵌入缓存保存超长文本的拆分断点
+        if !Self::column_exists(knowledge_pool, "embedding_cache", "split_offsets").await? {
+            sqlx::query("ALTER TABLE embedding_cache ADD COLUMN split_offsets TEXT")
+                .execute(knowledge_pool)
+                .await?;
+        }
+
+        // 迁移：分块元数据（标题路径等）
+        if !Self::column_exists(knowledge_pool, "knowledge_chunks", "metadata").await? {
+            sqlx::query("ALTER TABLE knowledge_chunks ADD COLUMN metadata TEXT")
+                .execute(knowledge_pool)
+                .await?;
+        }
+
+        // 迁移：分块全文索引（关键词检索）
+        Self::migrate_chunk_fts(knowledge_pool).await?;
+
+        // 迁移：搜索历史保存请求参数，用于重放
+        if !Self::column_exists(knowledge_pool, "search_history", "request").await? {
+            sqlx::query("ALTER TABLE search_history ADD COLUMN request TEXT")
+                .execute(knowledge_pool)
+                .await?;
+        }
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_search_history_collection ON search_history(collection_id, created_at)")
+            .execute(knowledge_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // 检查表中是否存在指定列
+    async fn column_exists(pool: &Pool<Sqlite>, table: &str, column: &str) -> Result<bool> {
+        let rows = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().any(|row| row.get::<String, _>("name") == column))
+    }
+
+    async fn migrate_embedding_model_provider(main_pool: &Pool<Sqlite>) -> Result<()> {
+        if Self::column_exists(main_pool, "embedding_models", "provider").await? {
+            return Ok(());
+        }
+
+        info!("Adding provider column to embedding_models...");
+        sqlx::query("ALTER TABLE embedding_models ADD COLUMN provider TEXT NOT NULL DEFAULT 'siliconflow'")
+            .execute(main_pool)
+            .await?;
+
         Ok(())
     }
 
     async fn migrate_collection_dimensions(knowledge_pool: &Pool<Sqlite>) -> Result<()> {
-        // 检查是否有vector_dimensions为384的集合
-        let count = sqlx::query(
-            "SELECT COUNT(*) as count FROM knowledge_collections WHERE vector_dimensions = 384"
+        // 旧版本只有一张 FLOAT[1024] 的向量表，集合上记录的维度（如384）并不可靠：
+        // 对应维度的向量表还不存在、但已在 knowledge_vectors 中写入过向量的集合，实际维度就是1024
+        let collections = sqlx::query(
+            "SELECT id, vector_dimensions, distance_metric FROM knowledge_collections WHERE vector_dimensions != ?"
         )
-        .fetch_one(knowledge_pool)
+        .bind(DEFAULT_VECTOR_DIMENSIONS)
+        .fetch_all(knowledge_pool)
         .await?;
 
-        let old_dimension_count: i64 = count.get("count");
-
-        if old_dimension_count > 0 {
-            info!("Found {} collections with 384 dimensions, migrating to 1024...", old_dimension_count);
+        for row in collections {
+            let collection_id: String = row.get("id");
+            let dimensions: i32 = row.get("vector_dimensions");
+            let metric = DistanceMetric::parse(row.get("distance_metric")).unwrap_or_default();
+
+            let table_exists = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+                .bind(vector_table_name(dimensions, metric))
+                .fetch_optional(knowledge_pool)
+                .await?
+                .is_some();
+            if table_exists || dimensions <= 0 {
+                continue;
+            }
 
-            // 更新所有384维的集合到1024维
-            sqlx::query(
-                "UPDATE knowledge_collections SET vector_dimensions = 1024 WHERE vector_dimensions = 384"
+            let legacy_vectors: i64 = sqlx::query(
+                "SELECT COUNT(*) FROM knowledge_vectors kv
+                 JOIN knowledge_chunks kc ON kv.rowid = kc.id
+                 WHERE kc.collection_id = ?"
             )
-            .execute(knowledge_pool)
+            .bind(&collection_id)
+            .fetch_one(knowledge_pool)
+            .await?
+            .get(0);
+
+            if legacy_vectors > 0 {
+                info!(
+                    "Collection {} declares {} dimensions but has {} vectors in knowledge_vectors, correcting to {}",
+                    collection_id, dimensions, legacy_vectors, DEFAULT_VECTOR_DIMENSIONS
+                );
+                sqlx::query("UPDATE knowledge_collections SET vector_dimensions = ? WHERE id = ?")
+                    .bind(DEFAULT_VECTOR_DIMENSIONS)
+                    .bind(&collection_id)
+                    .execute(knowledge_pool)
+                    .await?;
+            } else {
+                Self::create_vector_table(knowledge_pool, dimensions, metric).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn migrate_vector_partitions(knowledge_pool: &Pool<Sqlite>) -> Result<()> {
+        let legacy_tables = sqlx::query(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND sql LIKE 'CREATE VIRTUAL TABLE%' AND sql NOT LIKE '%partition key%'
+               AND (name = 'knowledge_vectors' OR name GLOB 'knowledge_vectors_[0-9]*')"
+        )
+        .fetch_all(knowledge_pool)
+        .await?;
+
+        for row in legacy_tables {
+            let table: String = row.get(0);
+            // 旧版向量表都是 L2 距离，表名为 knowledge_vectors 或 knowledge_vectors_<维度>
+            let dimensions = table
+                .strip_prefix("knowledge_vectors_")
+                .and_then(|d| d.parse::<i32>().ok())
+                .unwrap_or(DEFAULT_VECTOR_DIMENSIONS);
+
+            info!("Rebuilding vector table {} with collection_id partition key...", table);
+
+            // vec0 不支持 ALTER TABLE，借助临时表重建
+            let mut tx = knowledge_pool.begin().await?;
+            sqlx::query("DROP TABLE IF EXISTS temp.vector_migration").execute(&mut *tx).await?;
+            sqlx::query(&format!(
+                "CREATE TEMP TABLE vector_migration AS
+                 SELECT kv.rowid AS id, kc.collection_id, kv.embedding
+                 FROM {} kv JOIN knowledge_chunks kc ON kv.rowid = kc.id",
+                table
+            ))
+            .execute(&mut *tx)
             .await?;
+            sqlx::query(&format!("DROP TABLE {}", table)).execute(&mut *tx).await?;
+            sqlx::query(&format!(
+                "CREATE VIRTUAL TABLE {} USING vec0(collection_id TEXT PARTITION KEY, embedding FLOAT[{}])",
+                table, dimensions
+            ))
+            .execute(&mut *tx)
+            .await?;
+            let migrated = sqlx::query(&format!(
+                "INSERT INTO {} (rowid, collection_id, embedding) SELECT id, collection_id, embedding FROM temp.vector_migration",
+                table
+            ))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            sqlx::query("DROP TABLE temp.vector_migration").execute(&mut *tx).await?;
+            tx.commit().await?;
+
+            info!("Rebuilt vector table {} ({} vectors)", table, migrated);
+        }
+
+        Ok(())
+    }
+
+    async fn migrate_chunk_fts(knowledge_pool: &Pool<Sqlite>) -> Result<()> {
+        let fts_exists = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'knowledge_chunks_fts'")
+            .fetch_optional(knowledge_pool)
+            .await?
+            .is_some();
 
-            info!("Successfully migrated {} collections to 1024 dimensions", old_dimension_count);
+        // trigram 分词同时适用于中文和错误码、标识符等子串匹配
+        let queries = [
+            "CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_chunks_fts USING fts5(
+                chunk_text, content='knowledge_chunks', content_rowid='id', tokenize='trigram'
+            )",
+            "CREATE TRIGGER IF NOT EXISTS knowledge_chunks_fts_insert AFTER INSERT ON knowledge_chunks BEGIN
+                INSERT INTO knowledge_chunks_fts (rowid, chunk_text) VALUES (new.id, new.chunk_text);
+            END",
+            "CREATE TRIGGER IF NOT EXISTS knowledge_chunks_fts_delete AFTER DELETE ON knowledge_chunks BEGIN
+                INSERT INTO knowledge_chunks_fts (knowledge_chunks_fts, rowid, chunk_text) VALUES ('delete', old.id, old.chunk_text);
+            END",
+            "CREATE TRIGGER IF NOT EXISTS knowledge_chunks_fts_update AFTER UPDATE OF chunk_text ON knowledge_chunks BEGIN
+                INSERT INTO knowledge_chunks_fts (knowledge_chunks_fts, rowid, chunk_text) VALUES ('delete', old.id, old.chunk_text);
+                INSERT INTO knowledge_chunks_fts (rowid, chunk_text) VALUES (new.id, new.chunk_text);
+            END",
+        ];
+        for query in queries {
+            sqlx::query(query).execute(knowledge_pool).await?;
+        }
+
+        if !fts_exists {
+            info!("Building full-text index for existing chunks...");
+            sqlx::query("INSERT INTO knowledge_chunks_fts (knowledge_chunks_fts) VALUES ('rebuild')")
+                .execute(knowledge_pool)
+                .await?;
         }
 
         Ok(())
     }
 
-  
     // 获取主数据库连接池
     pub fn main_pool(&self) -> &Pool<Sqlite> {
         &self.main_pool
@@ -657,16 +1210,47 @@ impl DatabaseManager {
         &self.knowledge_pool
     }
 
+    // 应用数据目录（数据库文件所在目录）
+    pub fn app_dir(&self) -> &std::path::Path {
+        &self.app_dir
+    }
+
     // 清理查询缓存
     pub fn clear_cache(&self) {
         let mut cache = self.query_cache.lock().unwrap();
         cache.clear();
     }
 
+    // 清除某个集合的查询缓存（集合的向量发生变化时调用），包括内存和持久化的缓存
+    pub async fn invalidate_collection_cache(&self, collection_id: &str) -> Result<()> {
+        let removed = self.query_cache.lock().unwrap().invalidate_collection(collection_id);
+        let persisted = sqlx::query("DELETE FROM query_cache WHERE collection_id = ?")
+            .bind(collection_id)
+            .execute(self.knowledge_pool())
+            .await?
+            .rows_affected();
+
+        if removed > 0 || persisted > 0 {
+            println!("🗑️ [缓存] 集合 {} 数据已变更，清除 {} 个查询缓存条目（持久化 {} 条）", collection_id, removed, persisted);
+        }
+        Ok(())
+    }
+
+    // 设置查询缓存过期时间（秒），0 表示不缓存
+    pub fn set_cache_ttl(&self, ttl_seconds: u64) {
+        let mut cache = self.query_cache.lock().unwrap();
+        cache.ttl = std::time::Duration::from_secs(ttl_seconds);
+        cache.clear();
+    }
+
     // 获取缓存统计
     pub fn cache_stats(&self) -> (usize, usize) {
-        let cache = self.query_cache.lock().unwrap();
-        (cache.len(), cache.cap().get())
+        let stats = self.query_cache_stats();
+        (stats.entries, stats.capacity)
+    }
+
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        self.query_cache.lock().unwrap().stats()
     }
 }
 
@@ -674,10 +1258,12 @@ impl DatabaseManager {
 impl DatabaseManager {
     // 创建知识库集合
     pub async fn create_collection(&self, collection: &KnowledgeCollection) -> Result<()> {
+        self.ensure_vector_table(collection.vector_dimensions, collection.distance_metric).await?;
+
         sqlx::query(
             r#"
-            INSERT INTO knowledge_collections (id, name, description, embedding_model, vector_dimensions, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO knowledge_collections (id, name, description, embedding_model, vector_dimensions, distance_metric, chunking_strategy, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
         .bind(&collection.id)
@@ -685,6 +1271,8 @@ impl DatabaseManager {
         .bind(&collection.description)
         .bind(&collection.embedding_model)
         .bind(collection.vector_dimensions)
+        .bind(collection.distance_metric.as_str())
+        .bind(collection.chunking_strategy.as_str())
         .bind(collection.created_at.timestamp())
         .bind(collection.updated_at.timestamp())
         .execute(self.knowledge_pool())
@@ -697,7 +1285,7 @@ impl DatabaseManager {
     pub async fn get_collections(&self) -> Result<Vec<KnowledgeCollection>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, name, description, embedding_model, vector_dimensions, created_at, updated_at
+            SELECT id, name, description, embedding_model, vector_dimensions, created_at, updated_at, distance_metric, chunking_strategy
             FROM knowledge_collections
             ORDER BY created_at DESC
             "#
@@ -713,6 +1301,8 @@ impl DatabaseManager {
                 description: row.get(2),
                 embedding_model: row.get(3),
                 vector_dimensions: row.get(4),
+                distance_metric: DistanceMetric::parse(row.get(7)).unwrap_or_default(),
+                chunking_strategy: ChunkingStrategy::parse(row.get(8)).unwrap_or_default(),
                 created_at: DateTime::from_timestamp(row.get(5), 0).unwrap_or_default(),
                 updated_at: DateTime::from_timestamp(row.get(6), 0).unwrap_or_default(),
             });
@@ -762,8 +1352,8 @@ impl DatabaseManager {
             // 插入分块，使用自增ID
             let result = sqlx::query(
                 r#"
-                INSERT INTO knowledge_chunks (document_id, collection_id, chunk_index, chunk_text, token_count, created_at)
-                VALUES (?, ?, ?, ?, ?, ?)
+                INSERT INTO knowledge_chunks (document_id, collection_id, chunk_index, chunk_text, token_count, created_at, start_offset, end_offset, metadata)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
                 "#
             )
             .bind(&chunk.document_id)
@@ -772,6 +1362,9 @@ impl DatabaseManager {
             .bind(&chunk.chunk_text)
             .bind(chunk.token_count)
             .bind(chunk.created_at.timestamp())
+            .bind(chunk.start_offset)
+            .bind(chunk.end_offset)
+            .bind(&chunk.metadata)
             .execute(&mut *tx)
             .await?;
             
@@ -788,7 +1381,7 @@ impl DatabaseManager {
     pub async fn get_chunks_by_document_id(&self, document_id: &str) -> Result<Vec<KnowledgeChunk>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, document_id, collection_id, chunk_index, chunk_text, token_count, created_at
+            SELECT id, document_id, collection_id, chunk_index, chunk_text, token_count, created_at, start_offset, end_offset, metadata
             FROM knowledge_chunks
             WHERE document_id = ?
             ORDER BY chunk_index
@@ -798,25 +1391,47 @@ impl DatabaseManager {
         .fetch_all(self.knowledge_pool())
         .await?;
 
-        let mut chunks = Vec::new();
-        for row in rows {
-            let chunk = KnowledgeChunk {
-                id: row.get::<i64, _>("id"),
-                document_id: row.get("document_id"),
-                chunk_index: row.get("chunk_index"),
-                chunk_text: row.get("chunk_text"),
-                token_count: row.get("token_count"),
-                created_at: chrono::DateTime::from_timestamp(row.get::<i64, _>("created_at"), 0)
-                    .unwrap_or_default(),
-            };
-            chunks.push(chunk);
-        }
+        Ok(rows.iter().map(chunk_from_row).collect())
+    }
+
+    // 获取文档中 chunk_index 在 [from_index, to_index] 范围内的分块
+    pub async fn get_chunk_range(&self, document_id: &str, from_index: i32, to_index: i32) -> Result<Vec<KnowledgeChunk>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, document_id, collection_id, chunk_index, chunk_text, token_count, created_at, start_offset, end_offset, metadata
+            FROM knowledge_chunks
+            WHERE document_id = ? AND chunk_index BETWEEN ? AND ?
+            ORDER BY chunk_index
+            "#
+        )
+        .bind(document_id)
+        .bind(from_index)
+        .bind(to_index)
+        .fetch_all(self.knowledge_pool())
+        .await?;
 
-        Ok(chunks)
+        Ok(rows.iter().map(chunk_from_row).collect())
     }
 
     // 批量插入向量（使用新的分离式表结构）
     pub async fn insert_vectors(&self, vectors: &[VectorEmbedding]) -> Result<()> {
+        // 每个集合的向量写入与其维度对应的向量表，写入前校验维度
+        let mut tables = std::collections::HashMap::new();
+        for vector in vectors {
+            if !tables.contains_key(&vector.collection_id) {
+                let table = self.collection_vector_table(&vector.collection_id).await?;
+                tables.insert(vector.collection_id.clone(), table);
+            }
+
+            let table: &VectorTable = &tables[&vector.collection_id];
+            if vector.embedding.len() != table.dimensions as usize {
+                return Err(anyhow!(
+                    "向量维度 {} 与集合 {} 的向量维度 {} 不一致（chunk_id: {}）",
+                    vector.embedding.len(), vector.collection_id, table.dimensions, vector.chunk_id
+                ));
+            }
+        }
+
         let mut tx = self.knowledge_pool().begin().await?;
 
         for vector in vectors {
@@ -824,19 +1439,25 @@ impl DatabaseManager {
             let embedding_json = serde_json::to_string(&vector.embedding)?;
 
             // 直接使用chunk_id作为rowid（现在chunk_id已经是整数）
-            sqlx::query(
+            sqlx::query(&format!(
                 r#"
-                INSERT INTO knowledge_vectors (rowid, embedding)
-                VALUES (?, ?)
-                "#
-            )
+                INSERT INTO {} (rowid, collection_id, embedding)
+                VALUES (?, ?, ?)
+                "#,
+                tables[&vector.collection_id].name
+            ))
             .bind(vector.chunk_id)
+            .bind(&vector.collection_id)
             .bind(&embedding_json)
             .execute(&mut *tx)
             .await?;
         }
 
         tx.commit().await?;
+
+        for collection_id in tables.keys() {
+            self.invalidate_collection_cache(collection_id).await?;
+        }
         Ok(())
     }
 
@@ -845,48 +1466,186 @@ impl DatabaseManager {
         &self,
         query_embedding: &[f32],
         collection_id: &str,
+        embedding_model: &str,
         limit: usize,
         threshold: f32,
+        filters: Option<&SearchFilters>,
     ) -> Result<Vec<SearchResult>> {
-        let cache_key = format!("search:{}:{}:{}:{}",
-            collection_id,
-            limit,
-            threshold,
-            query_embedding.iter().map(|x| x.to_bits() as u64).sum::<u64>()
-        );
+        let filters = filters.filter(|f| !f.is_empty());
+
+        // 查询向量维度必须与集合存储的向量一致（例如集合使用降维输出时）
+        let vector_table = self.collection_vector_table(collection_id).await?;
+        if query_embedding.len() != vector_table.dimensions as usize {
+            return Err(anyhow!(
+                "查询向量维度 {} 与集合 {} 的向量维度 {} 不一致",
+                query_embedding.len(), collection_id, vector_table.dimensions
+            ));
+        }
+
+        let cache_key = search_cache_key(query_embedding, collection_id, embedding_model, &vector_table, limit, threshold, filters)?;
 
         // 检查缓存
         {
             let mut cache = self.query_cache.lock().unwrap();
             if let Some(cached_results) = cache.get(&cache_key) {
                 println!("🔍 [缓存] 返回缓存结果，集合: {}, 数量: {}", collection_id, cached_results.len());
-                return Ok(cached_results.clone());
+                return Ok(cached_results);
             }
         }
 
         println!("🔍 [搜索] 开始向量搜索，集合: {}, 限制: {}, 阈值: {}", collection_id, limit, threshold);
 
         // 使用 sqlite-vec 进行向量搜索
-        let results = self.search_vectors_with_vec_extension(query_embedding, collection_id, limit, threshold).await?;
+        let results = self.search_vectors_with_vec_extension(query_embedding, collection_id, &vector_table, limit, threshold, filters).await?;
 
         println!("🔍 [搜索] 搜索完成，找到 {} 个结果", results.len());
 
         // 缓存结果
         {
             let mut cache = self.query_cache.lock().unwrap();
-            cache.put(cache_key, results.clone());
+            cache.put(cache_key, collection_id, results.clone());
         }
 
         Ok(results)
     }
 
+    // 关键词检索（FTS5 BM25），score 为 BM25 分数取反，越大越相关。
+    // 查询词都少于3个字符时（如 "内存"）trigram 索引无法匹配，改用 LIKE，score 为命中的词数
+    pub async fn search_keywords(
+        &self,
+        query_text: &str,
+        collection_id: &str,
+        limit: usize,
+        filters: Option<&SearchFilters>,
+    ) -> Result<Vec<SearchResult>> {
+        let terms = keyword_terms(query_text);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (filter_sql, filter_args) = filters.filter(|f| !f.is_empty()).map(search_filter_sql).unwrap_or_default();
+        let filter_clause = if filter_sql.is_empty() {
+            String::new()
+        } else {
+            format!("AND {}", filter_sql)
+        };
+
+        let (sql, match_args) = match fts_match_query(&terms) {
+            Some(fts_query) => (Self::fts_keyword_sql(&filter_clause), vec![fts_query]),
+            None => {
+                let patterns: Vec<String> = terms.iter().map(|term| format!("%{}%", escape_like(term))).collect();
+                (Self::like_keyword_sql(terms.len(), &filter_clause), [patterns.clone(), patterns].concat())
+            }
+        };
+
+        let mut query = sqlx::query(&sql);
+        for arg in &match_args {
+            query = query.bind(arg);
+        }
+        query = query.bind(collection_id);
+        for arg in filter_args {
+            query = match arg {
+                FilterArg::Text(value) => query.bind(value),
+                FilterArg::Int(value) => query.bind(value),
+            };
+        }
+        let rows = query.bind(limit as i64).fetch_all(self.knowledge_pool()).await?;
+
+        println!("🔤 [关键词] 集合: {}, 找到 {} 个结果", collection_id, rows.len());
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let rank: f64 = row.get(5);
+                SearchResult {
+                    chunk_id: row.get::<i64, _>(0).to_string(),
+                    chunk_text: row.get(1),
+                    document_id: row.get(2),
+                    document_title: row.get(3),
+                    file_name: row.get(4),
+                    similarity: 0.0,
+                    score: -rank as f32,
+                    rerank_score: None,
+                    chunk_index: row.get(6),
+                    context: None,
+                    collection_id: collection_id.to_string(),
+                    normalized_score: None,
+                    start_offset: row.get(7),
+                    end_offset: row.get(8),
+                    highlights: Vec::new(),
+                    snippet: None,
+                    breadcrumb: ChunkMetadata::parse(row.get(9)).breadcrumb,
+                }
+            })
+            .collect())
+    }
+
+    // FTS5 关键词查询，参数依次为 MATCH 表达式、集合ID、过滤条件、数量
+    fn fts_keyword_sql(filter_clause: &str) -> String {
+        format!(
+            r#"
+            SELECT
+                kc.id,
+                kc.chunk_text,
+                kc.document_id,
+                kd.title,
+                kd.file_name,
+                bm25(knowledge_chunks_fts) AS rank,
+                kc.chunk_index,
+                kc.start_offset,
+                kc.end_offset,
+                kc.metadata
+            FROM knowledge_chunks_fts
+            JOIN knowledge_chunks kc ON knowledge_chunks_fts.rowid = kc.id
+            JOIN knowledge_documents kd ON kc.document_id = kd.id
+            WHERE knowledge_chunks_fts MATCH ? AND kc.collection_id = ?
+            {}
+            ORDER BY rank
+            LIMIT ?
+            "#,
+            filter_clause
+        )
+    }
+
+    // LIKE 关键词查询，rank 为命中词数取反（与 BM25 一致，越小越相关）。
+    // 参数依次为每个词的 LIKE 模式（命中计数与 WHERE 条件各一组）、集合ID、过滤条件、数量
+    fn like_keyword_sql(term_count: usize, filter_clause: &str) -> String {
+        let matched = vec!["(kc.chunk_text LIKE ? ESCAPE '\\')"; term_count];
+        format!(
+            r#"
+            SELECT
+                kc.id,
+                kc.chunk_text,
+                kc.document_id,
+                kd.title,
+                kd.file_name,
+                -CAST(({}) AS REAL) AS rank,
+                kc.chunk_index,
+                kc.start_offset,
+                kc.end_offset,
+                kc.metadata
+            FROM knowledge_chunks kc
+            JOIN knowledge_documents kd ON kc.document_id = kd.id
+            WHERE ({}) AND kc.collection_id = ?
+            {}
+            ORDER BY rank, kc.id
+            LIMIT ?
+            "#,
+            matched.join(" + "),
+            matched.join(" OR "),
+            filter_clause
+        )
+    }
+
     // 使用 sqlite-vec 扩展的向量搜索
     async fn search_vectors_with_vec_extension(
         &self,
         query_embedding: &[f32],
         collection_id: &str,
+        vector_table: &VectorTable,
         limit: usize,
         threshold: f32,
+        filters: Option<&SearchFilters>,
     ) -> Result<Vec<SearchResult>> {
         // 确保查询向量是L2归一化的（双重保险）
         let normalized_query = self.normalize_vector(query_embedding.to_vec());
@@ -895,30 +1654,62 @@ impl DatabaseManager {
         // 先获取更多结果，然后进行文档级别去重和质量筛选
         let fetch_limit = (limit * 3).min(100); // 最多获取100个结果
 
-        // 使用正确的sqlite-vec语法进行搜索
-        let rows = sqlx::query(
+        // 过滤条件通过 rowid IN 子查询传给 vec0，在 KNN 排序之前生效
+        let (filter_sql, filter_args) = filters.map(search_filter_sql).unwrap_or_default();
+        let filter_clause = if filter_sql.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "AND rowid IN (
+                    SELECT kc.id FROM knowledge_chunks kc
+                    JOIN knowledge_documents kd ON kc.document_id = kd.id
+                    WHERE kc.collection_id = ? AND {}
+                )",
+                filter_sql
+            )
+        };
+
+        // sqlite-vec KNN 查询（MATCH + k），按 collection_id 分区只扫描本集合的向量
+        let sql = format!(
             r#"
+            WITH knn AS (
+                SELECT rowid, distance
+                FROM {}
+                WHERE embedding MATCH ? AND k = ? AND collection_id = ?
+                {}
+            )
             SELECT
-                kv.rowid as chunk_id,
+                knn.rowid as chunk_id,
                 kc.chunk_text,
                 kc.document_id,
                 kd.title as document_title,
                 kd.file_name,
                 kc.chunk_index,
-                vec_distance_l2(kv.embedding, ?) as distance
-            FROM knowledge_vectors kv
-            JOIN knowledge_chunks kc ON kv.rowid = kc.id
+                knn.distance,
+                kc.start_offset,
+                kc.end_offset,
+                kc.metadata
+            FROM knn
+            JOIN knowledge_chunks kc ON knn.rowid = kc.id
             JOIN knowledge_documents kd ON kc.document_id = kd.id
-            WHERE kc.collection_id = ?
-            ORDER BY distance
-            LIMIT ?
-            "#
-        )
-        .bind(&query_bytes)
-        .bind(collection_id)
-        .bind(fetch_limit as i64)
-        .fetch_all(self.knowledge_pool())
-        .await?;
+            ORDER BY knn.distance
+            "#,
+            vector_table.name, filter_clause
+        );
+        let mut query = sqlx::query(&sql)
+            .bind(query_bytes)
+            .bind(fetch_limit as i64)
+            .bind(collection_id);
+        if !filter_args.is_empty() {
+            query = query.bind(collection_id);
+        }
+        for arg in filter_args {
+            query = match arg {
+                FilterArg::Text(value) => query.bind(value),
+                FilterArg::Int(value) => query.bind(value),
+            };
+        }
+        let rows = query.fetch_all(self.knowledge_pool()).await?;
 
         let mut results: Vec<SearchResult> = Vec::new();
         let mut seen_content_hashes = std::collections::HashSet::new();
@@ -937,12 +1728,10 @@ impl DatabaseManager {
 
         for row in rows {
             let distance: f64 = row.get(6); // distance现在是第7列（索引6）
-            let _chunk_index: i32 = row.get(5); // chunk_index是第6列（索引5）
+            let chunk_index: i32 = row.get(5); // chunk_index是第6列（索引5）
 
-            // 使用正确的L2距离转余弦相似度公式
-            // sqlite-vec返回的是L2距离，转换为余弦相似度
-            // 公式: similarity = 1.0 - (distance^2 / 2.0)
-            let similarity = 1.0 - ((distance * distance) / 2.0) as f32;
+            // 按集合的距离度量把距离换算为相似度
+            let similarity = vector_table.metric.similarity(distance);
 
             // 使用阈值过滤结果
             if similarity >= threshold {
@@ -974,6 +1763,16 @@ impl DatabaseManager {
                         file_name: row.get(4),
                         similarity,
                         score: similarity,
+                        rerank_score: None,
+                        chunk_index,
+                        context: None,
+                        collection_id: collection_id.to_string(),
+                        normalized_score: None,
+                        start_offset: row.get(7),
+                        end_offset: row.get(8),
+                        highlights: Vec::new(),
+                        snippet: None,
+                        breadcrumb: ChunkMetadata::parse(row.get(9)).breadcrumb,
                     };
 
                     // 按文档分组存储结果
@@ -1024,15 +1823,30 @@ impl DatabaseManager {
     
     // 删除文档及其相关数据
     pub async fn delete_document(&self, document_id: &str) -> Result<()> {
+        let collection_id: Option<String> = sqlx::query("SELECT collection_id FROM knowledge_documents WHERE id = ?")
+            .bind(document_id)
+            .fetch_optional(self.knowledge_pool())
+            .await?
+            .map(|row| row.get(0));
+
+        // 向量表在事务开始前查询，事务持有连接期间不再使用连接池的其他连接
+        let vector_table = match &collection_id {
+            Some(collection_id) => Some(self.collection_vector_table(collection_id).await?),
+            None => None,
+        };
+
         let mut tx = self.knowledge_pool().begin().await?;
 
         // 删除向量（使用rowid关联）
-        sqlx::query(
-            "DELETE FROM knowledge_vectors WHERE rowid IN (SELECT id FROM knowledge_chunks WHERE document_id = ?)"
-        )
-        .bind(document_id)
-        .execute(&mut *tx)
-        .await?;
+        if let Some(vector_table) = &vector_table {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE rowid IN (SELECT id FROM knowledge_chunks WHERE document_id = ?)",
+                vector_table.name
+            ))
+            .bind(document_id)
+            .execute(&mut *tx)
+            .await?;
+        }
 
         // 删除分块（级联删除）
         sqlx::query("DELETE FROM knowledge_chunks WHERE document_id = ?")
@@ -1048,8 +1862,10 @@ impl DatabaseManager {
 
         tx.commit().await?;
 
-        // 清理缓存
-        self.clear_cache();
+        // 清理该集合的查询缓存
+        if let Some(collection_id) = &collection_id {
+            self.invalidate_collection_cache(collection_id).await?;
+        }
 
         Ok(())
     }
@@ -1119,6 +1935,23 @@ impl DatabaseManager {
         }
     }
 
+    // 更新文档元数据（重新处理文档时同步请求中的元数据）。
+    // 检索过滤条件依赖文档元数据，更新后清除所在集合的查询缓存
+    pub async fn update_document_metadata(&self, document_id: &str, metadata: Option<&str>) -> Result<()> {
+        let row = sqlx::query("UPDATE knowledge_documents SET metadata = ?, updated_at = ? WHERE id = ? RETURNING collection_id")
+            .bind(metadata)
+            .bind(Utc::now().timestamp())
+            .bind(document_id)
+            .fetch_optional(self.knowledge_pool())
+            .await?;
+
+        if let Some(row) = row {
+            let collection_id: String = row.get(0);
+            self.invalidate_collection_cache(&collection_id).await?;
+        }
+        Ok(())
+    }
+
     // 获取系统配置
     pub async fn get_config(&self, key: &str) -> Result<Option<String>> {
         let row = sqlx::query("SELECT value FROM system_config WHERE key = ?")
@@ -1132,8 +1965,14 @@ impl DatabaseManager {
         }
     }
 
-    // 设置系统配置
+    // 设置系统配置，cache_ttl 先校验再写入，避免无效值被持久化
     pub async fn set_config(&self, key: &str, value: &str) -> Result<()> {
+        let cache_ttl = if key == "cache_ttl" {
+            Some(value.parse::<u64>().map_err(|_| anyhow!("无效的缓存过期时间: {}", value))?)
+        } else {
+            None
+        };
+
         sqlx::query(
             r#"
             INSERT OR REPLACE INTO system_config (key, value, updated_at)
@@ -1146,20 +1985,51 @@ impl DatabaseManager {
         .execute(self.knowledge_pool())
         .await?;
 
+        if let Some(ttl_seconds) = cache_ttl {
+            self.set_cache_ttl(ttl_seconds);
+        }
+
         Ok(())
     }
 
+    // 批量读取分块的向量（rowid 即 chunk_id）
+    pub async fn get_chunk_embeddings(&self, collection_id: &str, chunk_ids: &[i64]) -> Result<std::collections::HashMap<i64, Vec<f32>>> {
+        let mut embeddings = std::collections::HashMap::new();
+        if chunk_ids.is_empty() {
+            return Ok(embeddings);
+        }
+
+        let vector_table = self.collection_vector_table(collection_id).await?;
+        let sql = format!(
+            "SELECT rowid, embedding FROM {} WHERE rowid IN ({})",
+            vector_table.name,
+            vec!["?"; chunk_ids.len()].join(", ")
+        );
+        let mut query = sqlx::query(&sql);
+        for chunk_id in chunk_ids {
+            query = query.bind(chunk_id);
+        }
+
+        for row in query.fetch_all(self.knowledge_pool()).await? {
+            let blob: Vec<u8> = row.get(1);
+            embeddings.insert(row.get(0), embedding_from_blob(&blob));
+        }
+
+        Ok(embeddings)
+    }
+
     // 获取集合的向量数量
     pub async fn get_vector_count(&self, collection_id: &str) -> Result<usize> {
-        let result = sqlx::query(
+        let vector_table = self.collection_vector_table(collection_id).await?;
+        let result = sqlx::query(&format!(
             r#"
             SELECT COUNT(*) as count
-            FROM knowledge_vectors v
-            JOIN knowledge_chunks c ON v.chunk_id = c.id
-            JOIN knowledge_documents d ON c.document_id = d.id
-            WHERE d.collection_id = ?
-            "#
-        )
+            FROM {} v
+            JOIN knowledge_chunks c ON v.rowid = c.id
+            WHERE c.collection_id = ?
+            "#,
+            vector_table.name
+        ))
         .bind(collection_id)
         .fetch_one(self.knowledge_pool())
         .await?;
@@ -1168,6 +2038,245 @@ impl DatabaseManager {
         Ok(count as usize)
     }
 
+    // 创建指定维度的向量表（已存在时跳过），返回表名
+    pub async fn ensure_vector_table(&self, dimensions: i32, metric: DistanceMetric) -> Result<String> {
+        if dimensions <= 0 {
+            return Err(anyhow!("无效的向量维度: {}", dimensions));
+        }
+
+        Self::create_vector_table(self.knowledge_pool(), dimensions, metric).await
+    }
+
+    async fn create_vector_table(pool: &Pool<Sqlite>, dimensions: i32, metric: DistanceMetric) -> Result<String> {
+        let table = vector_table_name(dimensions, metric);
+        sqlx::query(&format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS {} USING vec0(collection_id TEXT PARTITION KEY, embedding FLOAT[{}] distance_metric={})",
+            table, dimensions, metric.vec0_metric()
+        ))
+        .execute(pool)
+        .await?;
+
+        Ok(table)
+    }
+
+    // 集合对应的向量表（表在创建集合和迁移时建好，这里只查找表名）
+    pub async fn collection_vector_table(&self, collection_id: &str) -> Result<VectorTable> {
+        let row = sqlx::query("SELECT vector_dimensions, distance_metric FROM knowledge_collections WHERE id = ?")
+            .bind(collection_id)
+            .fetch_optional(self.knowledge_pool())
+            .await?
+            .ok_or_else(|| anyhow!("集合 '{}' 不存在", collection_id))?;
+
+        let dimensions: i32 = row.get(0);
+        let metric = DistanceMetric::parse(row.get(1)).unwrap_or_default();
+        Ok(VectorTable { name: vector_table_name(dimensions, metric), dimensions, metric })
+    }
+
+    // 所有已创建的向量表（排除 vec0 的影子表）
+    pub async fn vector_tables(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND sql LIKE 'CREATE VIRTUAL TABLE%'
+               AND (name = 'knowledge_vectors' OR name GLOB 'knowledge_vectors_*')"
+        )
+        .fetch_all(self.knowledge_pool())
+        .await?;
+
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    // 读取未过期的持久化查询结果
+    pub async fn get_persisted_query(&self, query_hash: &str) -> Result<Option<Vec<SearchResult>>> {
+        let row = sqlx::query("SELECT results FROM query_cache WHERE query_hash = ? AND expires_at > ?")
+            .bind(query_hash)
+            .bind(Utc::now().timestamp())
+            .fetch_optional(self.knowledge_pool())
+            .await?;
+
+        match row.and_then(|row| row.get::<Option<String>, _>(0)) {
+            Some(results) => Ok(Some(serde_json::from_str(&results)?)),
+            None => Ok(None),
+        }
+    }
+
+    // 持久化查询结果，ttl_seconds 后过期
+    pub async fn put_persisted_query(
+        &self,
+        query_hash: &str,
+        query_text: &str,
+        collection_id: &str,
+        results: &[SearchResult],
+        ttl_seconds: u64,
+    ) -> Result<()> {
+        let now = Utc::now().timestamp();
+        sqlx::query(
+            "INSERT OR REPLACE INTO query_cache (id, query_hash, query_text, collection_id, results, created_at, expires_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(query_hash)
+        .bind(query_text)
+        .bind(collection_id)
+        .bind(serde_json::to_string(results)?)
+        .bind(now)
+        .bind(now + ttl_seconds as i64)
+        .execute(self.knowledge_pool())
+        .await?;
+
+        Ok(())
+    }
+
+    // 批量查询嵌入缓存，返回 text_hash -> 向量
+    pub async fn get_cached_embeddings(&self, model_id: &str, text_hashes: &[String]) -> Result<std::collections::HashMap<String, CachedEmbedding>> {
+        let mut cached = std::collections::HashMap::new();
+
+        // 分批查询，避免超过 SQLite 参数数量上限
+        for hashes in text_hashes.chunks(500) {
+            let placeholders = vec!["?"; hashes.len()].join(", ");
+            let sql = format!(
+                "SELECT text_hash, embedding, dimensions, split_offsets FROM embedding_cache WHERE model_id = ? AND text_hash IN ({})",
+                placeholders
+            );
+
+            let mut query = sqlx::query(&sql).bind(model_id);
+            for hash in hashes {
+                query = query.bind(hash);
+            }
+
+            for row in query.fetch_all(self.knowledge_pool()).await? {
+                let blob: Vec<u8> = row.get(1);
+                let dimensions = row.get::<i64, _>(2).max(1) as usize;
+                let split_offsets: Option<String> = row.get(3);
+                cached.insert(row.get(0), CachedEmbedding {
+                    // 拆分过的文本各片段向量依次拼接保存
+                    embeddings: embedding_from_blob(&blob).chunks(dimensions).map(|c| c.to_vec()).collect(),
+                    split_offsets: split_offsets
+                        .and_then(|offsets| serde_json::from_str(&offsets).ok())
+                        .unwrap_or_default(),
+                });
+            }
+        }
+
+        Ok(cached)
+    }
+
+    // 写入嵌入缓存
+    pub async fn put_cached_embeddings(&self, model_id: &str, entries: &[(String, CachedEmbedding)]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let now = Utc::now().timestamp();
+        let mut tx = self.knowledge_pool().begin().await?;
+        for (text_hash, entry) in entries {
+            let split_offsets = if entry.split_offsets.is_empty() {
+                None
+            } else {
+                Some(serde_json::to_string(&entry.split_offsets)?)
+            };
+            sqlx::query(
+                "INSERT OR REPLACE INTO embedding_cache (model_id, text_hash, dimensions, embedding, created_at, split_offsets) VALUES (?, ?, ?, ?, ?, ?)"
+            )
+            .bind(model_id)
+            .bind(text_hash)
+            .bind(entry.embeddings.first().map_or(0, |e| e.len()) as i64)
+            .bind(embedding_to_blob(&entry.embeddings.concat()))
+            .bind(now)
+            .bind(split_offsets)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // 清空嵌入缓存，返回删除的条目数
+    pub async fn clear_embedding_cache(&self) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM embedding_cache")
+            .execute(self.knowledge_pool())
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    // 记录一次嵌入调用的用量
+    pub async fn record_embedding_usage(&self, record: &EmbeddingUsageRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO embedding_usage
+                (provider, model_id, operation, collection_id, document_id, text_count, prompt_tokens, total_tokens, estimated, latency_ms, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(&record.provider)
+        .bind(&record.model_id)
+        .bind(&record.operation)
+        .bind(&record.collection_id)
+        .bind(&record.document_id)
+        .bind(record.text_count)
+        .bind(record.prompt_tokens)
+        .bind(record.total_tokens)
+        .bind(record.estimated)
+        .bind(record.latency_ms)
+        .bind(record.created_at.timestamp())
+        .execute(self.knowledge_pool())
+        .await?;
+
+        Ok(())
+    }
+
+    // 按日期 / 集合 / 模型汇总嵌入用量，时间范围为 Unix 时间戳（秒）
+    pub async fn get_embedding_usage_summary(
+        &self,
+        group_by: UsageGroupBy,
+        collection_id: Option<&str>,
+        since: Option<i64>,
+        until: Option<i64>,
+    ) -> Result<Vec<EmbeddingUsageSummary>> {
+        let key_expr = match group_by {
+            UsageGroupBy::Day => "strftime('%Y-%m-%d', created_at, 'unixepoch', 'localtime')",
+            UsageGroupBy::Collection => "COALESCE(collection_id, '')",
+            UsageGroupBy::Model => "model_id",
+        };
+
+        let sql = format!(
+            r#"
+            SELECT {key} AS usage_key,
+                   COUNT(*) AS calls,
+                   COALESCE(SUM(text_count), 0) AS text_count,
+                   COALESCE(SUM(prompt_tokens), 0) AS prompt_tokens,
+                   COALESCE(SUM(total_tokens), 0) AS total_tokens,
+                   COALESCE(AVG(latency_ms), 0) AS avg_latency_ms
+            FROM embedding_usage
+            WHERE (? IS NULL OR collection_id = ?)
+              AND (? IS NULL OR created_at >= ?)
+              AND (? IS NULL OR created_at < ?)
+            GROUP BY usage_key
+            ORDER BY usage_key
+            "#,
+            key = key_expr
+        );
+
+        let rows = sqlx::query(&sql)
+            .bind(collection_id)
+            .bind(collection_id)
+            .bind(since)
+            .bind(since)
+            .bind(until)
+            .bind(until)
+            .fetch_all(self.knowledge_pool())
+            .await?;
+
+        Ok(rows.iter().map(|row| EmbeddingUsageSummary {
+            key: row.get("usage_key"),
+            calls: row.get("calls"),
+            text_count: row.get("text_count"),
+            prompt_tokens: row.get("prompt_tokens"),
+            total_tokens: row.get("total_tokens"),
+            avg_latency_ms: row.get("avg_latency_ms"),
+        }).collect())
+    }
+
     // 获取数据库路径（用于调试）
     pub async fn get_database_path(&self) -> Result<String> {
         // 这是一个简化版本，实际路径应该在初始化时保存
@@ -1179,7 +2288,9 @@ impl DatabaseManager {
         info!("Resetting knowledge database...");
 
         // 删除所有向量
-        sqlx::query("DELETE FROM knowledge_vectors").execute(self.knowledge_pool()).await?;
+        for table in self.vector_tables().await? {
+            sqlx::query(&format!("DELETE FROM {}", table)).execute(self.knowledge_pool()).await?;
+        }
 
         // 删除所有分块
         sqlx::query("DELETE FROM knowledge_chunks").execute(self.knowledge_pool()).await?;
@@ -1191,6 +2302,7 @@ impl DatabaseManager {
         sqlx::query("DELETE FROM knowledge_collections").execute(self.knowledge_pool()).await?;
 
         // 清理缓存
+        sqlx::query("DELETE FROM query_cache").execute(self.knowledge_pool()).await?;
         self.clear_cache();
 
         info!("Knowledge database reset completed");
@@ -1240,11 +2352,14 @@ impl DatabaseManager {
     }
 
     // 清空查询缓存
-    pub fn clear_query_cache(&self) {
-        let mut cache = self.query_cache.lock().unwrap();
-        let cache_size = cache.len();
-        cache.clear();
-        println!("🗑️ [缓存] 查询缓存已清空，清空了 {} 个条目", cache_size);
+    pub async fn clear_query_cache(&self) -> Result<()> {
+        let cache_size = self.query_cache.lock().unwrap().clear();
+        let persisted = sqlx::query("DELETE FROM query_cache")
+            .execute(self.knowledge_pool())
+            .await?
+            .rows_affected();
+        println!("🗑️ [缓存] 查询缓存已清空，清空了 {} 个条目（持久化 {} 条）", cache_size, persisted);
+        Ok(())
     }
 }
 
@@ -1256,6 +2371,7 @@ impl DatabaseManager {
             knowledge_db: false,
             vec_extension: false,
             cache_stats: (0, 0),
+            query_cache: QueryCacheStats::default(),
         };
 
         // 检查主数据库
@@ -1281,6 +2397,7 @@ impl DatabaseManager {
 
         // 获取缓存统计
         health.cache_stats = self.cache_stats();
+        health.query_cache = self.query_cache_stats();
 
         Ok(health)
     }
@@ -1662,4 +2779,213 @@ mod tests {
         let db = DatabaseManager::new().await.unwrap();
         assert!(db.health_check().await.unwrap().main_db);
     }
+
+    #[test]
+    fn query_cache_expires_and_invalidates_per_collection() {
+        let mut cache = QueryCache::new(10, 60);
+        cache.put("a".to_string(), "c1", Vec::new());
+        cache.put("b".to_string(), "c2", Vec::new());
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("missing").is_none());
+
+        assert_eq!(cache.invalidate_collection("c1"), 1);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+
+        cache.entries.get_mut("b").unwrap().cached_at -= std::time::Duration::from_secs(61);
+        assert!(cache.get("b").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.expired, stats.invalidated), (2, 3, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn validates_cache_ttl_and_invalidates_on_metadata_change() {
+        let db = DatabaseManager::in_memory().await.unwrap();
+        assert!(db.set_config("cache_ttl", "一小时").await.is_err());
+        assert_eq!(db.get_config("cache_ttl").await.unwrap().as_deref(), Some("3600"));
+        db.set_config("cache_ttl", "600").await.unwrap();
+        assert_eq!(db.get_config("cache_ttl").await.unwrap().as_deref(), Some("600"));
+
+        let collection = KnowledgeCollection::new("文档".to_string(), "test-model".to_string(), 4);
+        db.create_collection(&collection).await.unwrap();
+        let document = KnowledgeDocument::new(collection.id.clone(), "Rust".to_string(), String::new(), None, None, None);
+        db.create_document(&document).await.unwrap();
+
+        db.query_cache.lock().unwrap().put("key".to_string(), &collection.id, Vec::new());
+        db.put_persisted_query("key", "内存安全", &collection.id, &[], 600).await.unwrap();
+
+        db.update_document_metadata(&document.id, Some(r#"{"author":"张三"}"#)).await.unwrap();
+        assert!(db.query_cache.lock().unwrap().get("key").is_none());
+        assert!(db.get_persisted_query("key").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn splits_cjk_queries_into_trigrams() {
+        assert_eq!(keyword_terms("内存安全怎么保证"), ["内存安", "存安全", "安全怎", "全怎么", "怎么保", "么保证"]);
+        assert_eq!(keyword_terms("Rust的所有权，是什么？"), ["Rust", "的所有", "所有权", "是什么"]);
+        assert_eq!(keyword_terms("a 内存 io"), ["内存", "io"]);
+        assert_eq!(keyword_terms("io 性能 tokio"), ["tokio"]);
+        assert_eq!(fts_match_query(&keyword_terms("内存 io")), None);
+        assert_eq!(fts_match_query(&keyword_terms("io tokio")).as_deref(), Some("\"tokio\""));
+        assert_eq!(escape_like(r"100%_\"), r"100\%\_\\");
+    }
+
+    #[tokio::test]
+    async fn deletes_document_with_single_connection_pool() {
+        let db = DatabaseManager::in_memory().await.unwrap();
+        let collection = KnowledgeCollection::new("文档".to_string(), "test-model".to_string(), 4);
+        db.create_collection(&collection).await.unwrap();
+        let document = KnowledgeDocument::new(collection.id.clone(), "Rust".to_string(), String::new(), None, None, None);
+        db.create_document(&document).await.unwrap();
+        db.create_chunks(&[KnowledgeChunk::new(document.id.clone(), 0, "内存安全".to_string(), 4)]).await.unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), db.delete_document(&document.id))
+            .await
+            .expect("删除文档不应等待连接池")
+            .unwrap();
+        assert!(db.get_document_by_id(&document.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn keyword_search_matches_cjk_queries() {
+        let db = DatabaseManager::in_memory().await.unwrap();
+        let collection = KnowledgeCollection::new("文档".to_string(), "test-model".to_string(), 4);
+        db.create_collection(&collection).await.unwrap();
+        let document = KnowledgeDocument::new(collection.id.clone(), "Rust".to_string(), String::new(), None, None, None);
+        db.create_document(&document).await.unwrap();
+        db.create_chunks(&[
+            KnowledgeChunk::new(document.id.clone(), 0, "Rust 通过所有权机制保证内存安全。".to_string(), 10),
+            KnowledgeChunk::new(document.id.clone(), 1, "借用检查器在编译期运行。".to_string(), 10),
+            KnowledgeChunk::new(document.id.clone(), 2, "折扣 100% 的商品".to_string(), 10),
+        ])
+        .await
+        .unwrap();
+
+        let results = db.search_keywords("内存安全怎么保证", &collection.id, 10, None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].chunk_index, 0);
+
+        // 两个字的词走 LIKE 兜底，命中词数多的排在前面
+        let results = db.search_keywords("内存 编译", &collection.id, 10, None).await.unwrap();
+        assert_eq!(results.iter().map(|r| r.chunk_index).collect::<Vec<_>>(), [0, 1]);
+        let results = db.search_keywords("内存 所有", &collection.id, 10, None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].score, 2.0);
+
+        // 通配符按字面匹配
+        let results = db.search_keywords("0%", &collection.id, 10, None).await.unwrap();
+        assert_eq!(results.iter().map(|r| r.chunk_index).collect::<Vec<_>>(), [2]);
+    }
+
+    // 基准：10 万条向量下，分区 KNN 查询与旧实现（无分区向量表 JOIN 分块表后按距离排序）的耗时对比
+    // 运行：cargo test --release vector_knn_benchmark -- --ignored --nocapture
+    #[tokio::test]
+    #[ignore]
+    async fn vector_knn_benchmark() {
+        use rand::Rng;
+        use std::time::Instant;
+
+        const DIMENSIONS: usize = 1024;
+        const TOTAL: i64 = 100_000;
+        const COLLECTIONS: i64 = 10;
+        const QUERIES: usize = 20;
+        const K: i64 = 30;
+
+        Lazy::force(&SQLITE_VEC_INIT);
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        sqlx::query(&format!(
+            "CREATE VIRTUAL TABLE bench_vectors USING vec0(collection_id TEXT PARTITION KEY, embedding FLOAT[{}])",
+            DIMENSIONS
+        ))
+        .execute(&pool)
+        .await
+        .unwrap();
+        // 旧实现的表结构：向量表没有分区键，集合信息在分块表中
+        sqlx::query(&format!("CREATE VIRTUAL TABLE legacy_vectors USING vec0(embedding FLOAT[{}])", DIMENSIONS))
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE legacy_chunks (id INTEGER PRIMARY KEY, collection_id TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("CREATE INDEX idx_legacy_chunks_collection ON legacy_chunks(collection_id)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut rng = rand::thread_rng();
+        let mut random_vector = || {
+            let v: Vec<f32> = (0..DIMENSIONS).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            v.into_iter().map(|x| x / norm).collect::<Vec<f32>>()
+        };
+
+        let started = Instant::now();
+        let mut tx = pool.begin().await.unwrap();
+        for id in 1..=TOTAL {
+            let collection_id = format!("collection-{}", id % COLLECTIONS);
+            let embedding = random_vector();
+            sqlx::query("INSERT INTO bench_vectors (rowid, collection_id, embedding) VALUES (?, ?, ?)")
+                .bind(id)
+                .bind(&collection_id)
+                .bind(embedding.as_bytes())
+                .execute(&mut *tx)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO legacy_vectors (rowid, embedding) VALUES (?, ?)")
+                .bind(id)
+                .bind(embedding.as_bytes())
+                .execute(&mut *tx)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO legacy_chunks (id, collection_id) VALUES (?, ?)")
+                .bind(id)
+                .bind(&collection_id)
+                .execute(&mut *tx)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+        println!("📦 写入 {} 条 {} 维向量: {:?}", TOTAL, DIMENSIONS, started.elapsed());
+
+        let queries: Vec<Vec<f32>> = (0..QUERIES).map(|_| random_vector()).collect();
+
+        let started = Instant::now();
+        for query in &queries {
+            let rows = sqlx::query("SELECT rowid, distance FROM bench_vectors WHERE embedding MATCH ? AND k = ? AND collection_id = ?")
+                .bind(query.as_bytes())
+                .bind(K)
+                .bind("collection-1")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+            assert_eq!(rows.len() as i64, K);
+        }
+        let knn = started.elapsed() / QUERIES as u32;
+
+        let started = Instant::now();
+        for query in &queries {
+            let rows = sqlx::query(
+                "SELECT kv.rowid, vec_distance_l2(kv.embedding, ?) AS distance
+                 FROM legacy_vectors kv
+                 JOIN legacy_chunks kc ON kv.rowid = kc.id
+                 WHERE kc.collection_id = ?
+                 ORDER BY distance LIMIT ?"
+            )
+            .bind(query.as_bytes())
+            .bind("collection-1")
+            .bind(K)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+            assert_eq!(rows.len() as i64, K);
+        }
+        let full_scan = started.elapsed() / QUERIES as u32;
+
+        println!("🔍 分区 KNN 平均耗时: {:?}", knn);
+        println!("🔍 旧实现（JOIN 后全表距离排序）平均耗时: {:?}", full_scan);
+    }
 }
\ No newline at end of file
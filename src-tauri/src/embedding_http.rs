@@ -0,0 +1,294 @@
+// 嵌入请求的HTTP公共设施：共享连接池、失败重试（指数退避 + 抖动）、按提供方限流
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+
+// 所有嵌入提供方共享的连接池
+pub static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(120))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(8)
+        .build()
+        .expect("failed to build embedding HTTP client")
+});
+
+/// 嵌入请求错误，保留状态码以便上层判断是否重试或拆分批次
+#[derive(Debug, thiserror::Error)]
+pub enum EmbeddingError {
+    #[error("{provider} 请求失败: 状态码 {status} - {body}")]
+    Http {
+        provider: String,
+        status: u16,
+        body: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("{provider} 请求发送失败: {message}")]
+    Network { provider: String, message: String },
+
+    #[error("{provider} 响应解析失败: {message}")]
+    InvalidResponse { provider: String, message: String },
+
+    #[error("{provider} 配置错误: {message}")]
+    Config { provider: String, message: String },
+}
+
+impl EmbeddingError {
+    // 限流、超时、服务端错误和网络错误可以重试
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            EmbeddingError::Http { status, .. } => {
+                *status == 408 || *status == 429 || (*status >= 500 && *status != 501)
+            }
+            EmbeddingError::Network { .. } => true,
+            _ => false,
+        }
+    }
+
+    // 请求体或单条文本超出提供方限制（413，或 400/422 且错误信息提到 token 上限）
+    pub fn is_payload_too_large(&self) -> bool {
+        match self {
+            EmbeddingError::Http { status: 413, .. } => true,
+            EmbeddingError::Http { status: 400 | 422, body, .. } => {
+                let body = body.to_lowercase();
+                body.contains("too long")
+                    || body.contains("too large")
+                    || body.contains("maximum context length")
+                    || (body.contains("token") && (body.contains("exceed") || body.contains("less than") || body.contains("limit")))
+            }
+            _ => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            EmbeddingError::Http { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// 重试策略
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub max_retry_after: Duration, // 服务端 Retry-After 的上限，避免无限期等待
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_retry_after: Duration::from_secs(120),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // 第 attempt 次重试前的等待时间（full jitter）
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        let millis = rand::thread_rng().gen_range(0..=exp.as_millis() as u64);
+        Duration::from_millis(millis)
+    }
+
+    fn delay_for(&self, error: &EmbeddingError, attempt: u32) -> Duration {
+        match error.retry_after() {
+            Some(retry_after) => retry_after.min(self.max_retry_after),
+            None => self.backoff(attempt),
+        }
+    }
+}
+
+// 解析 Retry-After 头（秒数或 HTTP 日期）
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+// 发送请求，对可重试的失败按策略退避重试；非成功状态码转换为 EmbeddingError
+pub async fn send_with_retry<F>(provider: &str, policy: &RetryPolicy, build: F) -> Result<reqwest::Response, EmbeddingError>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    let mut attempt = 0;
+    loop {
+        let error = match build().send().await {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => {
+                let status = response.status().as_u16();
+                let retry_after = parse_retry_after(response.headers());
+                let body = response.text().await.unwrap_or_else(|_| "未知错误".to_string());
+                EmbeddingError::Http { provider: provider.to_string(), status, body, retry_after }
+            }
+            Err(e) => EmbeddingError::Network { provider: provider.to_string(), message: e.to_string() },
+        };
+
+        if !error.is_retryable() || attempt >= policy.max_retries {
+            return Err(error);
+        }
+
+        let delay = policy.delay_for(&error, attempt);
+        attempt += 1;
+        println!("⏳ {} 请求失败（{}），{}ms 后进行第 {}/{} 次重试", provider, error, delay.as_millis(), attempt, policy.max_retries);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// 提供方限流配置
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimits {
+    pub max_concurrency: usize,
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 4,
+            tokens_per_minute: None,
+        }
+    }
+}
+
+struct TokenBucket {
+    available: f64,
+    last_refill: Instant,
+}
+
+// 单个提供方的并发与每分钟token限流
+pub struct ProviderLimiter {
+    semaphore: Arc<Semaphore>,
+    tokens_per_minute: Option<u32>,
+    bucket: Mutex<TokenBucket>,
+}
+
+impl ProviderLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(limits.max_concurrency.max(1))),
+            tokens_per_minute: limits.tokens_per_minute,
+            bucket: Mutex::new(TokenBucket {
+                available: limits.tokens_per_minute.unwrap_or(0) as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    // 获取并发许可并预扣 token 配额，许可在返回值释放时归还
+    pub async fn acquire(&self, estimated_tokens: usize) -> OwnedSemaphorePermit {
+        let permit = self.semaphore.clone().acquire_owned().await.expect("limiter semaphore closed");
+        if let Some(tokens_per_minute) = self.tokens_per_minute.filter(|t| *t > 0) {
+            self.wait_for_tokens(tokens_per_minute as f64, estimated_tokens as f64).await;
+        }
+        permit
+    }
+
+    async fn wait_for_tokens(&self, capacity: f64, tokens: f64) {
+        // 单次请求超过整分钟配额时，等待桶满后放行
+        let needed = tokens.min(capacity);
+        let refill_per_sec = capacity / 60.0;
+
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+                bucket.available = (bucket.available + elapsed * refill_per_sec).min(capacity);
+                bucket.last_refill = now;
+
+                if bucket.available >= needed {
+                    bucket.available -= needed;
+                    return;
+                }
+                Duration::from_secs_f64((needed - bucket.available) / refill_per_sec)
+            };
+
+            println!("⏳ 达到每分钟token限额，等待 {}ms", wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+// 粗略估算文本token数，用于限流预扣
+pub fn estimate_tokens(texts: &[String]) -> usize {
+    texts.iter().map(|t| t.len().div_ceil(4)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn backoff_stays_within_bounds() {
+        let policy = RetryPolicy::default();
+        for attempt in 0..10 {
+            assert!(policy.backoff(attempt) <= policy.max_delay);
+        }
+    }
+
+    #[test]
+    fn classifies_payload_too_large() {
+        let error = |status: u16, body: &str| EmbeddingError::Http {
+            provider: "stub".to_string(),
+            status,
+            body: body.to_string(),
+            retry_after: None,
+        };
+
+        assert!(error(413, "").is_payload_too_large());
+        assert!(error(400, r#"{"message":"input must have less than 8192 tokens"}"#).is_payload_too_large());
+        assert!(error(400, "This model's maximum context length is 8191 tokens").is_payload_too_large());
+        assert!(error(422, "Input is too long").is_payload_too_large());
+        assert!(!error(400, r#"{"message":"invalid api key"}"#).is_payload_too_large());
+        assert!(!error(500, "input is too long").is_payload_too_large());
+        assert!(!EmbeddingError::Network { provider: "stub".to_string(), message: "too large".to_string() }.is_payload_too_large());
+
+        // 超长被拒绝不应重试，限流和服务端错误应重试
+        assert!(!error(413, "").is_retryable());
+        assert!(error(429, "").is_retryable());
+        assert!(error(503, "").is_retryable());
+    }
+
+    #[tokio::test]
+    async fn retries_after_rate_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/embeddings", listener.local_addr().unwrap());
+
+        // 第一次返回 429，第二次返回成功
+        tokio::spawn(async move {
+            let responses = [
+                "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 4\r\nConnection: close\r\n\r\nslow",
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+            ];
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let response = send_with_retry("stub", &RetryPolicy::default(), || HTTP_CLIENT.get(&url)).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+    }
+}
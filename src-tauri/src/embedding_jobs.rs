@@ -0,0 +1,109 @@
+// 嵌入任务取消注册表 - 跟踪进行中的嵌入批次，支持前端一次性取消
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio_util::sync::CancellationToken;
+
+/// 嵌入任务被取消时返回的错误
+#[derive(Debug, thiserror::Error)]
+#[error("嵌入任务已取消")]
+pub struct EmbeddingCancelled;
+
+// 进行中的嵌入任务
+pub struct EmbeddingJobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<HashMap<u64, CancellationToken>>,
+}
+
+impl EmbeddingJobRegistry {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 登记一个新任务，返回的守卫在离开作用域时自动注销
+    pub fn start(&self) -> EmbeddingJobGuard<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+        self.jobs.lock().unwrap().insert(id, token.clone());
+        EmbeddingJobGuard { registry: self, id, token }
+    }
+
+    // 取消所有进行中的任务，返回被取消的数量
+    pub fn cancel_all(&self) -> usize {
+        let jobs = self.jobs.lock().unwrap();
+        for token in jobs.values() {
+            token.cancel();
+        }
+        jobs.len()
+    }
+}
+
+impl Default for EmbeddingJobRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct EmbeddingJobGuard<'a> {
+    registry: &'a EmbeddingJobRegistry,
+    id: u64,
+    token: CancellationToken,
+}
+
+impl EmbeddingJobGuard<'_> {
+    // 执行一个嵌入批次，任务被取消时立即返回 EmbeddingCancelled
+    pub async fn run<T, F>(&self, future: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        if self.token.is_cancelled() {
+            return Err(EmbeddingCancelled.into());
+        }
+
+        tokio::select! {
+            result = future => result,
+            _ = self.token.cancelled() => Err(EmbeddingCancelled.into()),
+        }
+    }
+}
+
+impl Drop for EmbeddingJobGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.jobs.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn cancel_all_stops_running_jobs() {
+        let registry = Arc::new(EmbeddingJobRegistry::new());
+
+        let job = tokio::spawn({
+            let registry = registry.clone();
+            async move {
+                let guard = registry.start();
+                guard.run(std::future::pending::<Result<()>>()).await
+            }
+        });
+
+        // 等待任务登记后再取消
+        while registry.jobs.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(registry.cancel_all(), 1);
+
+        let error = job.await.unwrap().unwrap_err();
+        assert!(error.downcast_ref::<EmbeddingCancelled>().is_some());
+        assert!(registry.jobs.lock().unwrap().is_empty());
+    }
+}
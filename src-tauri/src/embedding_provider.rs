@@ -0,0 +1,124 @@
+// 嵌入服务提供方抽象 - VectorService 通过注册表按模型的 provider 字段选择后端
+
+use crate::embedding_http::RateLimits;
+use crate::types::EmbeddingModel;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+// 内置的硅基流动提供方ID（embedding_models.provider 的默认值）
+pub const SILICONFLOW_PROVIDER_ID: &str = "siliconflow";
+
+/// 提供方返回的token用量
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: i64,
+    pub total_tokens: i64,
+}
+
+/// 一次嵌入调用的结果
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddingBatch {
+    pub embeddings: Vec<Vec<f32>>,
+    pub usage: Option<TokenUsage>, // 提供方未返回用量时为 None（如本地模型）
+}
+
+/// 嵌入后端统一接口
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// 提供方ID，与 embedding_models.provider 对应
+    fn name(&self) -> &str;
+
+    /// 单次请求允许的最大文本数量
+    fn max_batch(&self) -> usize;
+
+    /// 并发与每分钟token限额，VectorService 据此为每个提供方建立限流器
+    fn rate_limits(&self) -> RateLimits {
+        RateLimits::default()
+    }
+
+    /// 指定模型输出的向量维度
+    fn dimensions(&self, model: &EmbeddingModel) -> usize {
+        model.dimensions as usize
+    }
+
+    /// 是否支持输出较低维度（Matryoshka 模型），不支持的模型只能使用原生维度
+    fn supports_dimensions(&self, _model: &EmbeddingModel) -> bool {
+        false
+    }
+
+    /// 批量生成嵌入，返回顺序与输入一致，归一化由调用方负责
+    /// dimensions 仅在 supports_dimensions 返回 true 时传入
+    async fn embed_batch(&self, model: &EmbeddingModel, texts: &[String], api_key: &str, dimensions: Option<usize>) -> Result<EmbeddingBatch>;
+}
+
+// 硅基流动提供方
+pub struct SiliconFlowProvider;
+
+#[async_trait]
+impl EmbeddingProvider for SiliconFlowProvider {
+    fn name(&self) -> &str {
+        SILICONFLOW_PROVIDER_ID
+    }
+
+    fn max_batch(&self) -> usize {
+        // 硅基流动限制：batch 最大 32
+        32
+    }
+
+    fn supports_dimensions(&self, model: &EmbeddingModel) -> bool {
+        // 硅基流动仅 Qwen3-Embedding 系列支持 dimensions 参数
+        model.model_id.contains("Qwen3-Embedding")
+    }
+
+    async fn embed_batch(&self, model: &EmbeddingModel, texts: &[String], api_key: &str, dimensions: Option<usize>) -> Result<EmbeddingBatch> {
+        Ok(crate::siliconflow_embedding::siliconflow_batch_embeddings(api_key, texts, &model.model_id, dimensions).await?)
+    }
+}
+
+// 提供方注册表
+pub struct EmbeddingProviderRegistry {
+    providers: RwLock<HashMap<String, Arc<dyn EmbeddingProvider>>>,
+}
+
+impl EmbeddingProviderRegistry {
+    pub fn new() -> Self {
+        let registry = Self {
+            providers: RwLock::new(HashMap::new()),
+        };
+        registry.register(Arc::new(SiliconFlowProvider));
+        registry.register(Arc::new(crate::local_embedding::LocalEmbeddingProvider::new()));
+        registry
+    }
+
+    // 注册提供方，同名的旧实现会被替换
+    pub fn register(&self, provider: Arc<dyn EmbeddingProvider>) {
+        let mut providers = self.providers.write().unwrap();
+        println!("🔌 注册嵌入提供方: {}", provider.name());
+        providers.insert(provider.name().to_string(), provider);
+    }
+
+    // 按ID获取提供方
+    pub fn get(&self, provider_id: &str) -> Result<Arc<dyn EmbeddingProvider>> {
+        let providers = self.providers.read().unwrap();
+        providers
+            .get(provider_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("未注册的嵌入提供方: {}", provider_id))
+    }
+
+    // 已注册的提供方ID列表
+    pub fn provider_ids(&self) -> Vec<String> {
+        let providers = self.providers.read().unwrap();
+        let mut ids: Vec<String> = providers.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+}
+
+impl Default for EmbeddingProviderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
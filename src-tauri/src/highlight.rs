@@ -0,0 +1,157 @@
+// 搜索结果高亮 - 关键词命中位置、最相关句子和摘要（偏移均按字符计算，与分块偏移一致）
+
+use crate::types::{MatchSpan, Snippet};
+
+// 摘要在命中位置前后保留的字符数
+const SNIPPET_CONTEXT_CHARS: usize = 60;
+
+const SENTENCE_TERMINATORS: &[char] = &['。', '！', '？', '；', '.', '!', '?', ';', '\n'];
+
+fn lowercase_chars(text: &str) -> Vec<char> {
+    text.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect()
+}
+
+// 查询中的关键词：与关键词检索使用同一组查询词（中文按重叠的三字组拆分）
+fn query_terms(query: &str) -> Vec<Vec<char>> {
+    let mut terms: Vec<Vec<char>> = crate::database::keyword_terms(query)
+        .iter()
+        .map(|term| lowercase_chars(term))
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+// 关键词在文本中的命中位置（忽略大小写），按起始位置排序；
+// 重叠或相邻的命中合并为一段（三字组在连续的中文命中里彼此重叠）
+pub fn keyword_matches(text: &str, query: &str) -> Vec<MatchSpan> {
+    let text = lowercase_chars(text);
+    let mut spans: Vec<MatchSpan> = Vec::new();
+
+    for term in query_terms(query) {
+        for start in 0..(text.len() + 1).saturating_sub(term.len()) {
+            if text[start..start + term.len()] == term[..] {
+                spans.push(MatchSpan { start, end: start + term.len() });
+            }
+        }
+    }
+
+    spans.sort_by_key(|span| span.start);
+    let mut merged: Vec<MatchSpan> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+// 把文本切分为句子，返回每个句子的字符范围（去掉首尾空白）
+fn sentence_spans(chars: &[char]) -> Vec<MatchSpan> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for (i, c) in chars.iter().enumerate() {
+        if SENTENCE_TERMINATORS.contains(c) || i + 1 == chars.len() {
+            let mut span = MatchSpan { start, end: i + 1 };
+            while span.start < span.end && chars[span.start].is_whitespace() {
+                span.start += 1;
+            }
+            while span.end > span.start && chars[span.end - 1].is_whitespace() {
+                span.end -= 1;
+            }
+            if span.end > span.start {
+                spans.push(span);
+            }
+            start = i + 1;
+        }
+    }
+    spans
+}
+
+fn bigrams(chars: &[char]) -> std::collections::HashSet<(char, char)> {
+    chars.windows(2)
+        .filter(|w| !w[0].is_whitespace() && !w[1].is_whitespace())
+        .map(|w| (w[0], w[1]))
+        .collect()
+}
+
+// 与查询最相关的句子：按字符二元组重合度估计（无需再调用嵌入模型，中英文都适用）。
+// 没有任何重合时返回第一句
+pub fn best_sentence(text: &str, query: &str) -> Option<MatchSpan> {
+    let chars = lowercase_chars(text);
+    let query_bigrams = bigrams(&lowercase_chars(query));
+    let sentences = sentence_spans(&chars);
+
+    let mut best: Option<(MatchSpan, f32)> = None;
+    for span in sentences.iter().copied() {
+        let sentence_bigrams = bigrams(&chars[span.start..span.end]);
+        if sentence_bigrams.is_empty() {
+            continue;
+        }
+        let overlap = sentence_bigrams.intersection(&query_bigrams).count() as f32;
+        // 按句子长度开方归一化，避免长句仅凭长度胜出
+        let score = overlap / (sentence_bigrams.len() as f32).sqrt();
+        if overlap > 0.0 && best.map_or(true, |(_, best_score)| score > best_score) {
+            best = Some((span, score));
+        }
+    }
+
+    best.map(|(span, _)| span).or_else(|| sentences.first().copied())
+}
+
+// 以第一个命中为中心截取摘要，摘要中的高亮位置相对摘要文本
+pub fn build_snippet(text: &str, highlights: &[MatchSpan]) -> Option<Snippet> {
+    let first = highlights.first()?;
+    let chars: Vec<char> = text.chars().collect();
+
+    let start = first.start.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let end = (first.end + SNIPPET_CONTEXT_CHARS).min(chars.len());
+
+    Some(Snippet {
+        text: chars[start..end].iter().collect(),
+        start,
+        highlights: highlights.iter()
+            .filter(|span| span.start >= start && span.end <= end)
+            .map(|span| MatchSpan { start: span.start - start, end: span.end - start })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_keyword_matches_and_best_sentence() {
+        let text = "Rust 的所有权系统。借用检查器保证内存安全！Tokio 是异步运行时。";
+
+        let spans = keyword_matches(text, "tokio 借用检查器");
+        let matched: Vec<String> = spans.iter()
+            .map(|s| text.chars().skip(s.start).take(s.end - s.start).collect())
+            .collect();
+        assert_eq!(matched, vec!["借用检查器", "Tokio"]);
+
+        let sentence = best_sentence(text, "怎样保证内存安全").unwrap();
+        let sentence_text: String = text.chars().skip(sentence.start).take(sentence.end - sentence.start).collect();
+        assert_eq!(sentence_text, "借用检查器保证内存安全！");
+
+        let snippet = build_snippet(text, &spans).unwrap();
+        assert_eq!(snippet.start, 0);
+        assert_eq!(snippet.highlights, spans);
+    }
+
+    #[test]
+    fn highlights_unspaced_cjk_queries() {
+        let text = "Rust 的所有权系统。借用检查器保证内存安全！";
+
+        // 不带空格的中文查询按三字组命中，重叠的命中合并为一段
+        let spans = keyword_matches(text, "内存安全怎么保证");
+        assert_eq!(spans, vec![MatchSpan { start: 19, end: 23 }]);
+
+        // 与关键词检索一致：有三字以上的词时，两个字的词不参与命中
+        let spans = keyword_matches(text, "所有权 内存");
+        assert_eq!(spans, vec![MatchSpan { start: 6, end: 9 }]);
+        assert_eq!(keyword_matches(text, "内存").len(), 1);
+    }
+}
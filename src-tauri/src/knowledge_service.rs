@@ -1,4 +1,9 @@
+use crate::chunking::{
+    chunker_for, embedding_input, model_tokenizer, tokenizer_path, Chunker, MarkdownChunker, TextChunk, TokenChunker,
+    TokenLimitedChunker, DEFAULT_CHUNK_OVERLAP_TOKENS, DEFAULT_CHUNK_TOKENS,
+};
 use crate::database::DatabaseManager;
+use crate::highlight;
 use crate::vector_service::VectorService;
 use crate::types::*;
 use anyhow::{Result, anyhow};
@@ -6,7 +11,284 @@ use tracing::warn;
 use std::sync::Arc;
 use chrono::Utc;
 use sqlx::Row;
-use unicode_segmentation::UnicodeSegmentation;
+
+// 集合要求的输出维度：集合维度小于模型原生维度时按集合维度生成（Matryoshka 降维）
+fn collection_output_dimensions(collection: &KnowledgeCollection, model: &EmbeddingModel) -> Option<usize> {
+    (collection.vector_dimensions > 0 && collection.vector_dimensions < model.dimensions)
+        .then_some(collection.vector_dimensions as usize)
+}
+
+// 倒数排名融合（RRF）：score = w / (k + 向量排名) + (1 - w) / (k + 关键词排名)
+const RRF_K: f32 = 60.0;
+
+fn reciprocal_rank_fusion(
+    vector_results: Vec<SearchResult>,
+    keyword_results: Vec<SearchResult>,
+    vector_weight: f32,
+    limit: usize,
+) -> Vec<SearchResult> {
+    let mut fused: Vec<SearchResult> = Vec::new();
+    let mut positions: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+
+    let ranked = [(vector_results, vector_weight), (keyword_results, 1.0 - vector_weight)];
+    for (results, weight) in ranked {
+        for (rank, mut result) in results.into_iter().enumerate() {
+            let contribution = weight / (RRF_K + rank as f32 + 1.0);
+            match positions.get(&result.chunk_id) {
+                Some(&index) => fused[index].score += contribution,
+                None => {
+                    positions.insert(result.chunk_id.clone(), fused.len());
+                    result.score = contribution;
+                    fused.push(result);
+                }
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    fused.truncate(limit);
+    fused
+}
+
+// 去掉请求中的 API 密钥（用于缓存键和搜索历史）
+fn without_api_keys(request: &SearchRequest) -> SearchRequest {
+    let mut request = request.clone();
+    request.api_key.clear();
+    if let Some(rerank) = request.rerank.as_mut() {
+        rerank.api_key = None;
+    }
+    request
+}
+
+// 持久化查询缓存的键：规范化后的请求（不含 API 密钥）加上集合的向量配置，取 SHA-256
+fn query_cache_key(request: &SearchRequest, collection: &KnowledgeCollection, limit: usize, threshold: f32) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut normalized = without_api_keys(request);
+    normalized.collection_id = Some(collection.id.clone());
+    normalized.limit = Some(limit);
+    normalized.threshold = Some(threshold);
+    normalized.embedding_model = Some(collection.embedding_model.clone());
+
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_string(&normalized)?.as_bytes());
+    hasher.update(format!("{}:{}", collection.vector_dimensions, collection.distance_metric.as_str()).as_bytes());
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn search_history_from_row(row: &sqlx::sqlite::SqliteRow) -> SearchHistory {
+    SearchHistory {
+        id: row.get(0),
+        query_text: row.get(1),
+        collection_id: row.get(2),
+        results_count: row.get::<i64, _>(3) as usize,
+        execution_time_ms: row.get::<i64, _>(4) as u64,
+        created_at: chrono::DateTime::from_timestamp(row.get(5), 0).unwrap_or_default(),
+        request: row.get::<Option<String>, _>(6).and_then(|json| serde_json::from_str(&json).ok()),
+    }
+}
+
+// 标注命中位置和摘要：关键词/混合检索标出关键词，向量检索（或没有关键词命中时）标出最相关的句子
+fn annotate_matches(results: &mut [SearchResult], query: &str, mode: SearchMode) {
+    for result in results {
+        let mut highlights = if mode == SearchMode::Vector {
+            Vec::new()
+        } else {
+            highlight::keyword_matches(&result.chunk_text, query)
+        };
+        if highlights.is_empty() {
+            highlights.extend(highlight::best_sentence(&result.chunk_text, query));
+        }
+        result.snippet = highlight::build_snippet(&result.chunk_text, &highlights);
+        result.highlights = highlights;
+    }
+}
+
+// 字符分块参数（没有分词器时使用）：请求未显式提供时按模型采用推荐值，其他模型使用系统配置
+fn chunk_parameters(embedding_model: &str, chunk_size: Option<usize>, chunk_overlap: Option<usize>, config: &SystemConfig) -> (usize, usize) {
+    let model_id = embedding_model.to_lowercase();
+    let chunk_size = chunk_size.unwrap_or(if model_id.contains("bge-m3") {
+        900 // 建议 800-1024，取中位偏上
+    } else if model_id.contains("bge-large-zh") {
+        480 // 安全上限，避免超过512 tokens
+    } else if model_id.contains("bge-large-en") {
+        900 // 建议 800-1024
+    } else {
+        config.chunk_size
+    });
+    let chunk_overlap = chunk_overlap.unwrap_or(if model_id.contains("bge-m3") {
+        120 // 建议 100-150
+    } else if model_id.contains("bge-large-zh") {
+        80 // 建议 50-100
+    } else if model_id.contains("bge-large-en") {
+        100 // 建议 80-120
+    } else {
+        config.chunk_overlap
+    });
+    (chunk_size, chunk_overlap)
+}
+
+fn to_knowledge_chunks(chunks: Vec<TextChunk>) -> Vec<KnowledgeChunk> {
+    chunks
+        .into_iter()
+        .map(|chunk| {
+            let mut knowledge_chunk = KnowledgeChunk::new(
+                "temp_doc_id".to_string(), // 将在插入时替换为实际ID
+                chunk.index as i32,
+                chunk.text,
+                chunk.token_count as i32,
+            ).with_offsets(chunk.start_offset, chunk.end_offset);
+            if chunk.breadcrumb.is_some() {
+                let metadata = ChunkMetadata { breadcrumb: chunk.breadcrumb };
+                knowledge_chunk.metadata = serde_json::to_string(&metadata).ok();
+            }
+            knowledge_chunk
+        })
+        .collect()
+}
+
+// 结果的排名分数：有重排序分数时优先使用
+fn ranking_score(result: &SearchResult) -> f32 {
+    result.rerank_score.unwrap_or(result.score)
+}
+
+// min-max 归一化到 0-1，分数全部相同时记为 1
+fn min_max_normalize(scores: &[f32]) -> Vec<f32> {
+    let (min, max) = scores.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &x| (lo.min(x), hi.max(x)));
+    scores.iter().map(|&x| if max > min { (x - min) / (max - min) } else { 1.0 }).collect()
+}
+
+// 跨集合按排名融合：不同集合的模型、度量和检索方式不同，原始分数不可直接比较，
+// 集合内 min-max 又会把弱集合的最差命中拉到与强集合相同的区间，因此只使用集合内排名。
+// 分数为 (k + 1) / (k + 排名)，各集合第一名为 1
+fn rank_scores(results: &mut [SearchResult]) {
+    let mut order: Vec<usize> = (0..results.len()).collect();
+    order.sort_by(|&a, &b| ranking_score(&results[b]).partial_cmp(&ranking_score(&results[a])).unwrap_or(std::cmp::Ordering::Equal));
+    for (rank, index) in order.into_iter().enumerate() {
+        results[index].normalized_score = Some((RRF_K + 1.0) / (RRF_K + rank as f32 + 1.0));
+    }
+}
+
+// 拼接连续分块，去掉相邻分块之间的重叠部分（有偏移时按偏移计算，旧数据按最长公共前后缀）
+fn join_chunk_texts(chunks: &[KnowledgeChunk]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&KnowledgeChunk> = None;
+
+    for chunk in chunks {
+        let overlap = match previous {
+            Some(prev) => match (prev.end_offset, chunk.start_offset) {
+                (Some(prev_end), Some(start)) => (prev_end - start).max(0) as usize,
+                _ => {
+                    let prev_chars: Vec<char> = prev.chunk_text.chars().collect();
+                    let chars: Vec<char> = chunk.chunk_text.chars().collect();
+                    (1..=prev_chars.len().min(chars.len()))
+                        .rev()
+                        .find(|&n| prev_chars[prev_chars.len() - n..] == chars[..n])
+                        .unwrap_or(0)
+                }
+            },
+            None => 0,
+        };
+
+        text.extend(chunk.chunk_text.chars().skip(overlap));
+        previous = Some(chunk);
+    }
+
+    text
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 { 0.0 } else { dot / (norm_a * norm_b) }
+}
+
+// 贪心选取结果：MMR = λ·相关性 - (1-λ)·与已选结果的最大相似度，同时限制每个文档的结果数。
+// 相关性取重排序分数（没有时取 score），先归一化到 0-1，使不同检索方式的分数尺度一致
+fn select_diverse(
+    results: Vec<SearchResult>,
+    embeddings: &[Option<Vec<f32>>],
+    mmr_lambda: Option<f32>,
+    max_chunks_per_document: Option<usize>,
+    limit: usize,
+) -> Vec<SearchResult> {
+    let relevance = min_max_normalize(&results.iter().map(ranking_score).collect::<Vec<_>>());
+    let lambda = mmr_lambda.map(|l| l.clamp(0.0, 1.0));
+
+    let mut remaining: Vec<usize> = (0..results.len()).collect();
+    let mut selected: Vec<usize> = Vec::new();
+    let mut per_document: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+
+    while selected.len() < limit {
+        remaining.retain(|&i| {
+            max_chunks_per_document.map_or(true, |cap| per_document.get(results[i].document_id.as_str()).copied().unwrap_or(0) < cap)
+        });
+
+        let best = remaining.iter().enumerate().map(|(position, &i)| {
+            let score = match lambda {
+                Some(lambda) => {
+                    let redundancy = selected.iter()
+                        .filter_map(|&j| Some(cosine(embeddings[i].as_ref()?, embeddings[j].as_ref()?)))
+                        .fold(0.0_f32, f32::max);
+                    lambda * relevance[i] - (1.0 - lambda) * redundancy
+                }
+                None => relevance[i],
+            };
+            (position, score)
+        })
+        // 分数相同时保留原有顺序
+        .fold(None, |best: Option<(usize, f32)>, (position, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((position, score)),
+        });
+
+        let Some((position, _)) = best else { break };
+        let index = remaining.remove(position);
+        *per_document.entry(results[index].document_id.as_str()).or_insert(0) += 1;
+        selected.push(index);
+    }
+
+    let mut results: Vec<Option<SearchResult>> = results.into_iter().map(Some).collect();
+    selected.into_iter().filter_map(|i| results[i].take()).collect()
+}
+
+// 用重排序模型对候选重新排序；重排序失败时保留原始顺序
+async fn rerank_results(
+    query: &str,
+    api_key: &str,
+    options: &RerankOptions,
+    results: Vec<SearchResult>,
+    limit: usize,
+) -> Vec<SearchResult> {
+    let url = options.url.as_deref().unwrap_or(crate::rerank::SILICONFLOW_RERANK_URL);
+    let model = options.model.as_deref().unwrap_or(crate::rerank::DEFAULT_RERANK_MODEL);
+    let api_key = options.api_key.as_deref().unwrap_or(api_key);
+    let documents: Vec<String> = results.iter().map(|r| r.chunk_text.clone()).collect();
+
+    let scores = match crate::rerank::rerank(url, api_key, model, query, &documents).await {
+        Ok(scores) => scores,
+        Err(e) => {
+            warn!("Rerank failed, keeping retrieval order: {}", e);
+            return results.into_iter().take(limit).collect();
+        }
+    };
+
+    let mut candidates: Vec<Option<SearchResult>> = results.into_iter().map(Some).collect();
+    let reranked: Vec<SearchResult> = scores
+        .into_iter()
+        .filter(|(_, score)| options.min_score.map_or(true, |min| *score >= min))
+        .filter_map(|(index, score)| {
+            let mut result = candidates[index].take()?;
+            result.rerank_score = Some(score);
+            Some(result)
+        })
+        .take(limit)
+        .collect();
+
+    println!("🔁 [重排序] 候选 {} 条，保留 {} 条", documents.len(), reranked.len());
+    reranked
+}
 
 // 文档处理器
 pub struct DocumentProcessor {
@@ -19,6 +301,24 @@ impl DocumentProcessor {
         Self { db, vector_service }
     }
 
+    // 获取要重新处理的文档，请求带有元数据时同步更新，避免过滤条件使用旧元数据
+    async fn existing_document(&self, document_id: &str, request: &DocumentProcessRequest) -> Result<KnowledgeDocument> {
+        let mut document = self.db.get_document_by_id(document_id).await
+            .map_err(|e| anyhow!("获取现有文档失败: {}", e))?;
+
+        if let Some(metadata) = &request.metadata {
+            let current: Option<std::collections::HashMap<String, String>> = document.metadata.as_deref()
+                .and_then(|json| serde_json::from_str(json).ok());
+            if current.as_ref() != Some(metadata) {
+                let metadata = serde_json::to_string(metadata)?;
+                self.db.update_document_metadata(&document.id, Some(&metadata)).await?;
+                document.metadata = Some(metadata);
+            }
+        }
+
+        Ok(document)
+    }
+
     // 处理文档（带API密钥）
     pub async fn process_document_with_api_key(&self, request: DocumentProcessRequest, api_key: &str) -> Result<DocumentProcessResponse> {
         // 基本处理和之前一样，但嵌入生成时使用API密钥
@@ -41,12 +341,11 @@ impl DocumentProcessor {
         let document = if let Some(existing_id) = &request.document_id {
             // 使用现有文档
             println!("📝 使用现有文档处理: {}", existing_id);
-            self.db.get_document_by_id(existing_id).await
-                .map_err(|e| anyhow!("获取现有文档失败: {}", e))?
+            self.existing_document(existing_id, &request).await?
         } else {
             // 创建新文档记录
             println!("📝 创建新文档记录: {}", request.title);
-            let new_doc = KnowledgeDocument::new(
+            let mut new_doc = KnowledgeDocument::new(
                 request.collection_id.clone(),
                 request.title.clone(),
                 request.content.clone(),
@@ -54,6 +353,7 @@ impl DocumentProcessor {
                 request.file_size,
                 request.mime_type.clone(),
             );
+            new_doc.metadata = request.metadata.as_ref().map(serde_json::to_string).transpose()?;
 
             // 批量插入数据 - 创建文档
             self.db.create_document(&new_doc).await?;
@@ -73,34 +373,12 @@ impl DocumentProcessor {
                 chunks_count: existing_chunks.len(),
                 vectors_count: existing_chunks.len(), // 假设每个chunk都有对应的vector
                 processing_time_ms: start_time.elapsed().as_millis() as u64,
+                split_chunks: Vec::new(),
             });
         }
 
-        // 分块处理：按模型采用推荐 chunk 参数（请求未显式提供时）
-        let model_id = collection.embedding_model.to_lowercase();
-        let mut chunk_size = request.chunk_size.unwrap_or(config.chunk_size);
-        let mut chunk_overlap = request.chunk_overlap.unwrap_or(config.chunk_overlap);
-        if request.chunk_size.is_none() {
-            chunk_size = if model_id.contains("bge-m3") {
-                900 // 建议 800-1024，取中位偏上
-            } else if model_id.contains("bge-large-zh") {
-                480 // 安全上限，避免超过512 tokens
-            } else if model_id.contains("bge-large-en") {
-                900 // 建议 800-1024
-            } else { chunk_size };
-        }
-        if request.chunk_overlap.is_none() {
-            chunk_overlap = if model_id.contains("bge-m3") {
-                120 // 建议 100-150
-            } else if model_id.contains("bge-large-zh") {
-                80 // 建议 50-100
-            } else if model_id.contains("bge-large-en") {
-                100 // 建议 80-120
-            } else { chunk_overlap };
-        }
-        println!("🧩 [分块参数] 模型: {}, chunk_size: {}, overlap: {}", collection.embedding_model, chunk_size, chunk_overlap);
-
-        let mut chunks = self.chunk_document(&request.content, chunk_size, chunk_overlap).await?;
+        let chunker = self.collection_chunker(&collection, None, request.chunk_size, request.chunk_overlap, request.chunk_size_unit, &config).await?;
+        let chunks = to_knowledge_chunks(chunker.chunk(&request.content));
 
         // 验证分块数量 - 提高到5000个块，但给出警告
         if chunks.len() > 5000 {
@@ -110,21 +388,8 @@ impl DocumentProcessor {
         }
 
         // 生成嵌入向量 - 使用API密钥调用实际服务
-        // 安全截断：避免单条文本超出模型 token 限制导致 413
-        // 以字符近似 token 限制：CJK 1字符≈1token，其他 4字符≈1token。目标≤512 tokens
-        let safe_texts: Vec<String> = chunks.iter().map(|c| {
-            let s = c.chunk_text.as_str();
-            let is_cjk = s.chars().any(|ch| (ch >= '\u{4E00}' && ch <= '\u{9FFF}') || (ch >= '\u{3400}' && ch <= '\u{4DBF}'));
-            let max_chars = if is_cjk { 512 } else { 2048 };
-            let count = s.chars().count();
-            if count > max_chars { s.chars().take(max_chars).collect::<String>() } else { s.to_string() }
-        }).collect();
-
-        let embeddings = self.generate_embeddings_with_api_key(
-            &safe_texts,
-            &collection.embedding_model,
-            api_key
-        ).await?;
+        // 超出模型输入限制的分块由嵌入层拆分，拆分出的片段各自作为分块入库
+        let (mut chunks, embeddings, split_chunks) = self.embed_chunks(chunks, &collection, &document.id, api_key, chunker.as_ref()).await?;
 
         // 更新所有块的document_id为实际ID
         for chunk in &mut chunks {
@@ -151,6 +416,7 @@ impl DocumentProcessor {
             chunks_count: chunks.len(),
             vectors_count: vector_embeddings.len(),
             processing_time_ms: processing_time.as_millis() as u64,
+            split_chunks,
         })
     }
 
@@ -164,6 +430,75 @@ impl DocumentProcessor {
         Ok(embeddings)
     }
 
+    // 生成分块嵌入，超出模型输入限制的分块拆成多个片段，返回重新编号后的分块及对应向量
+    async fn embed_chunks(
+        &self,
+        chunks: Vec<KnowledgeChunk>,
+        collection: &KnowledgeCollection,
+        document_id: &str,
+        api_key: &str,
+        chunker: &dyn Chunker,
+    ) -> Result<(Vec<KnowledgeChunk>, Vec<Vec<f32>>, Vec<SplitChunkInfo>)> {
+        let model = self.vector_service.get_embedding_model(&collection.embedding_model).await?;
+        let context = EmbeddingContext::ingest(&collection.id, document_id)
+            .with_dimensions(collection_output_dimensions(collection, &model));
+        // 标题路径只加在嵌入输入中，分块文本保持原文
+        let breadcrumbs: Vec<Option<String>> = chunks.iter()
+            .map(|c| ChunkMetadata::parse(c.metadata.as_deref()).breadcrumb)
+            .collect();
+        let texts: Vec<String> = chunks.iter()
+            .zip(&breadcrumbs)
+            .map(|(c, breadcrumb)| embedding_input(&c.chunk_text, breadcrumb.as_deref()))
+            .collect();
+        let results = self.vector_service.generate_split_embeddings_with_api_key(&texts, &model, api_key, &context).await?;
+
+        let mut expanded = Vec::with_capacity(chunks.len());
+        let mut embeddings = Vec::with_capacity(chunks.len());
+        let mut split_chunks = Vec::new();
+
+        for ((mut chunk, mut pieces), breadcrumb) in chunks.into_iter().zip(results).zip(breadcrumbs) {
+            if pieces.len() == 1 {
+                let piece = pieces.remove(0);
+                chunk.chunk_index = expanded.len() as i32;
+                expanded.push(chunk);
+                embeddings.push(piece.embedding);
+                continue;
+            }
+
+            println!("✂️ 分块 {} 超出模型输入限制，已拆分为 {} 段", chunk.chunk_index, pieces.len());
+            split_chunks.push(SplitChunkInfo {
+                original_index: chunk.chunk_index as usize,
+                pieces: pieces.len(),
+            });
+            // 片段按顺序完整覆盖原分块，偏移依次累加
+            // 第一段以标题路径开头，去掉后才是原文
+            let prefix = embedding_input("", breadcrumb.as_deref());
+            let mut piece_start = chunk.start_offset;
+            for (i, piece) in pieces.into_iter().enumerate() {
+                let text = match piece.text.strip_prefix(&prefix) {
+                    Some(text) if i == 0 => text.to_string(),
+                    _ => piece.text,
+                };
+                let token_count = chunker.count_tokens(&text);
+                let piece_end = piece_start.map(|start| start + text.chars().count() as i64);
+                let mut piece_chunk = KnowledgeChunk::new(
+                    chunk.document_id.clone(),
+                    expanded.len() as i32,
+                    text,
+                    token_count as i32,
+                );
+                piece_chunk.start_offset = piece_start;
+                piece_chunk.end_offset = piece_end;
+                piece_chunk.metadata = chunk.metadata.clone();
+                expanded.push(piece_chunk);
+                embeddings.push(piece.embedding);
+                piece_start = piece_end;
+            }
+        }
+
+        Ok((expanded, embeddings, split_chunks))
+    }
+
     // 生成单个文本的嵌入向量（带API密钥）
     pub async fn generate_embedding_with_api_key(&self, text: &str, model_id: &str, api_key: &str) -> Result<Vec<f32>> {
         let embeddings = self.generate_embeddings_with_api_key(&[text.to_string()], model_id, api_key).await?;
@@ -191,12 +526,11 @@ impl DocumentProcessor {
         let document = if let Some(existing_id) = &request.document_id {
             // 使用现有文档
             println!("📝 使用现有文档处理: {}", existing_id);
-            self.db.get_document_by_id(existing_id).await
-                .map_err(|e| anyhow!("获取现有文档失败: {}", e))?
+            self.existing_document(existing_id, &request).await?
         } else {
             // 创建新文档记录
             println!("📝 创建新文档记录: {}", request.title);
-            let new_doc = KnowledgeDocument::new(
+            let mut new_doc = KnowledgeDocument::new(
                 request.collection_id.clone(),
                 request.title.clone(),
                 request.content.clone(),
@@ -204,37 +538,15 @@ impl DocumentProcessor {
                 request.file_size,
                 request.mime_type.clone(),
             );
+            new_doc.metadata = request.metadata.as_ref().map(serde_json::to_string).transpose()?;
 
             // 批量插入数据 - 创建文档
             self.db.create_document(&new_doc).await?;
             new_doc
         };
 
-        // 分块处理：按模型采用推荐 chunk 参数（请求未显式提供时）
-        let model_id = collection.embedding_model.to_lowercase();
-        let mut chunk_size = request.chunk_size.unwrap_or(config.chunk_size);
-        let mut chunk_overlap = request.chunk_overlap.unwrap_or(config.chunk_overlap);
-        if request.chunk_size.is_none() {
-            chunk_size = if model_id.contains("bge-m3") {
-                900
-            } else if model_id.contains("bge-large-zh") {
-                640
-            } else if model_id.contains("bge-large-en") {
-                900
-            } else { chunk_size };
-        }
-        if request.chunk_overlap.is_none() {
-            chunk_overlap = if model_id.contains("bge-m3") {
-                120
-            } else if model_id.contains("bge-large-zh") {
-                80
-            } else if model_id.contains("bge-large-en") {
-                100
-            } else { chunk_overlap };
-        }
-        println!("🧩 [分块参数] 模型: {}, chunk_size: {}, overlap: {}", collection.embedding_model, chunk_size, chunk_overlap);
-
-        let mut chunks = self.chunk_document(&request.content, chunk_size, chunk_overlap).await?;
+        let chunker = self.collection_chunker(&collection, None, request.chunk_size, request.chunk_overlap, request.chunk_size_unit, &config).await?;
+        let chunks = to_knowledge_chunks(chunker.chunk(&request.content));
 
         // 验证分块数量 - 提高到5000个块，但给出警告
         if chunks.len() > 5000 {
@@ -244,18 +556,7 @@ impl DocumentProcessor {
         }
 
         // 生成嵌入向量
-        let safe_texts: Vec<String> = chunks.iter().map(|c| {
-            let s = c.chunk_text.as_str();
-            let is_cjk = s.chars().any(|ch| (ch >= '\u{4E00}' && ch <= '\u{9FFF}') || (ch >= '\u{3400}' && ch <= '\u{4DBF}'));
-            let max_chars = if is_cjk { 512 } else { 2048 };
-            let count = s.chars().count();
-            if count > max_chars { s.chars().take(max_chars).collect::<String>() } else { s.to_string() }
-        }).collect();
-
-        let embeddings = self.vector_service.generate_embeddings_batch(
-            &safe_texts,
-            &collection.embedding_model,
-        ).await?;
+        let (mut chunks, embeddings, split_chunks) = self.embed_chunks(chunks, &collection, &document.id, "", chunker.as_ref()).await?;
 
         // 更新所有块的document_id为实际ID
         for chunk in &mut chunks {
@@ -282,52 +583,106 @@ impl DocumentProcessor {
             chunks_count: chunks.len(),
             vectors_count: vector_embeddings.len(),
             processing_time_ms: processing_time.as_millis() as u64,
+            split_chunks,
         })
     }
 
-    // 文档分块
-    async fn chunk_document(&self, content: &str, chunk_size: usize, chunk_overlap: usize) -> Result<Vec<KnowledgeChunk>> {
-        let mut chunks = Vec::new();
-        let graphemes = content.graphemes(true).collect::<Vec<_>>();
-        let total_chars = graphemes.len();
-
-        if total_chars == 0 {
-            return Ok(chunks);
-        }
+    // 分块预览：使用与入库相同的参数和分块器；指定集合时采用集合的模型和分块策略
+    pub async fn preview_chunks(
+        &self,
+        content: &str,
+        collection_id: Option<&str>,
+        chunk_size: Option<usize>,
+        chunk_overlap: Option<usize>,
+        unit: ChunkSizeUnit,
+        strategy: Option<ChunkingStrategy>,
+    ) -> Result<Vec<TextChunk>> {
+        let config = self.get_system_config().await?;
+        let chunker = match collection_id {
+            Some(id) => {
+                let collection = self.get_collection(id).await?;
+                self.collection_chunker(&collection, strategy, chunk_size, chunk_overlap, unit, &config).await?
+            }
+            None if unit == ChunkSizeUnit::Tokens => {
+                return Err(anyhow!("按 token 分块需要指定集合（使用集合模型的分词器）"));
+            }
+            None => {
+                let (chunk_size, chunk_overlap) = chunk_parameters("", chunk_size, chunk_overlap, &config);
+                chunker_for(strategy.unwrap_or_default(), chunk_size, chunk_overlap)
+            }
+        };
 
-        let mut start = 0;
-        let mut chunk_index = 0;
+        Ok(chunker.chunk(content))
+    }
 
-        while start < total_chars {
-            let end = (start + chunk_size).min(total_chars);
-            let chunk_text = graphemes[start..end].concat();
+    // 集合使用的分块器。模型有分词器时按 token 分块，token 数按分词器计算，每块不超过模型的 max_tokens：
+    // chunk_size / chunk_overlap 按 unit 解释，调用方显式按字符指定时先按字符切分，超限的块再按 token 拆分。
+    // 没有分词器时才按字符分块（按模型的经验值，块大小不超过 max_tokens），此时不能按 token 指定
+    async fn collection_chunker(
+        &self,
+        collection: &KnowledgeCollection,
+        strategy: Option<ChunkingStrategy>,
+        chunk_size: Option<usize>,
+        chunk_overlap: Option<usize>,
+        unit: ChunkSizeUnit,
+        config: &SystemConfig,
+    ) -> Result<Box<dyn Chunker>> {
+        let strategy = strategy.unwrap_or(collection.chunking_strategy);
+        let model = self.vector_service.get_embedding_model(&collection.embedding_model).await?;
+        let max_tokens = model.max_tokens.max(1) as usize;
+
+        let Some(tokenizer) = model_tokenizer(&model, self.db.app_dir()).await else {
+            if unit == ChunkSizeUnit::Tokens {
+                return Err(anyhow!(
+                    "模型 {} 没有分词器 {:?}，无法按 token 分块",
+                    model.name, tokenizer_path(&model, self.db.app_dir())
+                ));
+            }
 
-            // 计算token数量（简化计算）
-            let token_count = (chunk_text.len() + 3) / 4; // 粗略估算
+            let (chunk_size, chunk_overlap) = chunk_parameters(&collection.embedding_model, chunk_size, chunk_overlap, config);
+            let chunk_size = chunk_size.min(max_tokens);
+            println!(
+                "🧩 [分块参数] 模型: {}, 策略: {}, 没有分词器，按字符分块: chunk_size: {}, overlap: {}, 模型上限: {}",
+                model.name, strategy.as_str(), chunk_size, chunk_overlap, model.max_tokens
+            );
+            return Ok(chunker_for(strategy, chunk_size, chunk_overlap));
+        };
 
-            chunks.push(KnowledgeChunk::new(
-                "temp_doc_id".to_string(), // 将在插入时替换为实际ID
-                chunk_index,
-                chunk_text,
-                token_count as i32,
-            ));
-
-            start = if end >= total_chars {
-                total_chars
-            } else {
-                end.saturating_sub(chunk_overlap)
-            };
+        // Markdown 分块中超长的段落按句子边界拆分
+        let token_strategy = if strategy == ChunkingStrategy::Markdown { ChunkingStrategy::Sentence } else { strategy };
 
-            chunk_index += 1;
+        if let (ChunkSizeUnit::Chars, Some(chunk_size)) = (unit, chunk_size) {
+            let chunk_overlap = chunk_overlap.unwrap_or(config.chunk_overlap);
+            println!(
+                "🧩 [分块参数] 模型: {}, 策略: {}, 按字符分块: chunk_size: {}, overlap: {}, 超过模型上限 {} tokens 的块按 token 拆分",
+                model.name, strategy.as_str(), chunk_size, chunk_overlap, model.max_tokens
+            );
+            let limit = TokenChunker::new(tokenizer, token_strategy, max_tokens, DEFAULT_CHUNK_OVERLAP_TOKENS, max_tokens);
+            return Ok(Box::new(TokenLimitedChunker::new(chunker_for(strategy, chunk_size, chunk_overlap), limit)));
         }
 
-        Ok(chunks)
+        let chunker = TokenChunker::new(
+            tokenizer,
+            token_strategy,
+            chunk_size.unwrap_or(DEFAULT_CHUNK_TOKENS),
+            chunk_overlap.unwrap_or(DEFAULT_CHUNK_OVERLAP_TOKENS),
+            max_tokens,
+        );
+        println!(
+            "🧩 [分块参数] 模型: {}, 策略: {}, 按 token 分块: {} tokens, overlap: {}, 模型上限: {}",
+            model.name, strategy.as_str(), chunker.chunk_tokens(), chunker.chunk_overlap(), model.max_tokens
+        );
+        if strategy == ChunkingStrategy::Markdown {
+            let limit = chunker.chunk_tokens();
+            return Ok(Box::new(MarkdownChunker::new(Box::new(chunker), limit, true)));
+        }
+        Ok(Box::new(chunker))
     }
 
     // 获取集合
     async fn get_collection(&self, collection_id: &str) -> Result<KnowledgeCollection> {
         let row = sqlx::query(
-            "SELECT id, name, description, embedding_model, vector_dimensions, created_at, updated_at
+            "SELECT id, name, description, embedding_model, vector_dimensions, created_at, updated_at, distance_metric, chunking_strategy
              FROM knowledge_collections WHERE id = ?"
         )
         .bind(collection_id)
@@ -340,6 +695,8 @@ impl DocumentProcessor {
             description: row.get(2),
             embedding_model: row.get(3),
             vector_dimensions: row.get(4),
+            distance_metric: DistanceMetric::parse(row.get(7)).unwrap_or_default(),
+            chunking_strategy: ChunkingStrategy::parse(row.get(8)).unwrap_or_default(),
             created_at: chrono::DateTime::from_timestamp(row.get(5), 0).unwrap_or_default(),
             updated_at: chrono::DateTime::from_timestamp(row.get(6), 0).unwrap_or_default(),
         })
@@ -372,6 +729,7 @@ impl DocumentProcessor {
 }
 
 // 知识库搜索服务
+#[derive(Clone)]
 pub struct KnowledgeSearchService {
     db: Arc<DatabaseManager>,
     vector_service: Arc<VectorService>,
@@ -394,7 +752,7 @@ impl KnowledgeSearchService {
         println!("🔍 [调试] 系统配置: default_collection='{}'", config.default_collection);
 
         // 确定搜索集合
-        let collection_id = request.collection_id.unwrap_or_else(|| {
+        let collection_id = request.collection_id.clone().unwrap_or_else(|| {
             println!("🔍 [调试] 使用默认集合: '{}'", config.default_collection);
             config.default_collection.clone()
         });
@@ -425,9 +783,190 @@ impl KnowledgeSearchService {
         };
 
         // 使用合理的阈值设置
-        let mut threshold = request.threshold.unwrap_or(0.3); // 使用合理的阈值
+        let threshold = request.threshold.unwrap_or(0.3); // 使用合理的阈值
         println!("🔧 [阈值调整] 使用阈值: {:.3}", threshold);
 
+        // 持久化查询缓存：相同的问题和参数直接返回上次的结果，跳过嵌入和检索
+        let query_cache_key = if config.cache_ttl > 0 && self.is_query_cache_enabled().await? {
+            Some(query_cache_key(&request, &collection, limit, threshold)?)
+        } else {
+            None
+        };
+        if let Some(key) = &query_cache_key {
+            match self.db.get_persisted_query(key).await {
+                Ok(Some(results)) => {
+                    println!("💾 [查询缓存] 命中持久化缓存，集合: {}, 数量: {}", collection_id, results.len());
+                    return self.finish_search(&request, collection, results, start_time).await;
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to read query cache: {}", e),
+            }
+        }
+
+        // 需要重排序或多样化时先取回更多候选
+        let diversify = request.mmr_lambda.is_some() || request.max_chunks_per_document.is_some();
+        let retrieval_limit = match &request.rerank {
+            Some(options) => options.candidates.unwrap_or(limit * 3).max(limit),
+            None if diversify => limit * 3,
+            None => limit,
+        };
+
+        // 混合检索先多取一些候选，融合排名后再截断
+        let mode = request.mode.unwrap_or_default();
+        let candidate_limit = if mode == SearchMode::Hybrid { retrieval_limit * 3 } else { retrieval_limit };
+
+        let mut results = if mode == SearchMode::Keyword {
+            self.db.search_keywords(&request.query, &collection_id, retrieval_limit, request.filters.as_ref()).await?
+        } else {
+            self.vector_search(&request, &collection, candidate_limit, threshold).await?
+        };
+
+        if mode == SearchMode::Hybrid {
+            let keyword_results = self.db.search_keywords(&request.query, &collection_id, candidate_limit, request.filters.as_ref()).await?;
+            let vector_weight = request.hybrid_weight.unwrap_or(0.5).clamp(0.0, 1.0);
+            println!("🔀 [混合检索] 向量 {} 条 + 关键词 {} 条，向量权重 {:.2}", results.len(), keyword_results.len(), vector_weight);
+            results = reciprocal_rank_fusion(results, keyword_results, vector_weight, retrieval_limit);
+        }
+
+        if let Some(options) = &request.rerank {
+            let keep = if diversify { retrieval_limit } else { limit };
+            results = rerank_results(&request.query, &request.api_key, options, results, keep).await;
+        }
+
+        if diversify {
+            results = self.diversify_results(&collection_id, results, &request, limit).await?;
+        }
+
+        if request.context_chunks.is_some() || request.merge_adjacent {
+            results = self.expand_context(results, request.context_chunks.unwrap_or(0), request.merge_adjacent).await?;
+        }
+
+        annotate_matches(&mut results, &request.query, mode);
+
+        if let Some(key) = &query_cache_key {
+            if let Err(e) = self.db.put_persisted_query(key, &request.query, &collection_id, &results, config.cache_ttl as u64).await {
+                warn!("Failed to write query cache: {}", e);
+            }
+        }
+
+        self.finish_search(&request, collection, results, start_time).await
+    }
+
+    // 记录搜索历史并组装响应
+    async fn finish_search(
+        &self,
+        request: &SearchRequest,
+        collection: KnowledgeCollection,
+        results: Vec<SearchResult>,
+        start_time: std::time::Instant,
+    ) -> Result<SearchResponse> {
+        let collection_id = collection.id;
+
+        // 记录搜索历史
+        if self.is_search_history_enabled().await? {
+            self.record_search_history(request, &collection_id, results.len(), start_time.elapsed()).await?;
+        }
+
+        let query_time = start_time.elapsed();
+        let total_count = results.len();
+
+        Ok(SearchResponse {
+            results,
+            total_count,
+            query_time_ms: query_time.as_millis() as u64,
+            collection_id,
+            embedding_model: collection.embedding_model,
+        })
+    }
+
+    // 为结果附加前后相邻分块组成的段落；merge_adjacent 时同一文档中窗口相邻或重叠的结果合并为一条
+    async fn expand_context(&self, results: Vec<SearchResult>, context_chunks: usize, merge_adjacent: bool) -> Result<Vec<SearchResult>> {
+        let radius = context_chunks as i32;
+
+        // 每条输出：代表结果（排名最靠前的命中）、窗口范围及窗口内命中的分块
+        let mut passages: Vec<(SearchResult, i32, i32, Vec<String>)> = results.into_iter()
+            .map(|result| {
+                let (from, to) = ((result.chunk_index - radius).max(0), result.chunk_index + radius);
+                let chunk_id = result.chunk_id.clone();
+                (result, from, to, vec![chunk_id])
+            })
+            .collect();
+
+        // 合并同一文档中相邻或重叠的窗口，保留排名靠前的结果作为代表；合并后窗口会扩大，需重复检查
+        if merge_adjacent {
+            let mut i = 0;
+            while i < passages.len() {
+                let adjacent = (i + 1..passages.len()).find(|&j| {
+                    passages[i].0.document_id == passages[j].0.document_id
+                        && passages[j].1 <= passages[i].2 + 1 && passages[j].2 >= passages[i].1 - 1
+                });
+                match adjacent {
+                    Some(j) => {
+                        let (_, start, end, matched) = passages.remove(j);
+                        passages[i].1 = passages[i].1.min(start);
+                        passages[i].2 = passages[i].2.max(end);
+                        passages[i].3.extend(matched);
+                    }
+                    None => i += 1,
+                }
+            }
+        }
+
+        let mut expanded = Vec::with_capacity(passages.len());
+        for (mut result, from, to, matched_chunk_ids) in passages {
+            let chunks = self.db.get_chunk_range(&result.document_id, from, to).await?;
+            if let (Some(first), Some(last)) = (chunks.first(), chunks.last()) {
+                result.context = Some(ResultContext {
+                    text: join_chunk_texts(&chunks),
+                    start_chunk_index: first.chunk_index,
+                    end_chunk_index: last.chunk_index,
+                    start_offset: chunks.iter().all(|c| c.start_offset.is_some()).then_some(first.start_offset).flatten(),
+                    end_offset: chunks.iter().all(|c| c.end_offset.is_some()).then_some(last.end_offset).flatten(),
+                    matched_chunk_ids,
+                });
+            }
+            expanded.push(result);
+        }
+
+        Ok(expanded)
+    }
+
+    // 结果多样化：MMR 需要候选分块的向量，从向量表中读取
+    async fn diversify_results(
+        &self,
+        collection_id: &str,
+        results: Vec<SearchResult>,
+        request: &SearchRequest,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let embeddings = match request.mmr_lambda {
+            Some(_) => {
+                let chunk_ids: Vec<i64> = results.iter().filter_map(|r| r.chunk_id.parse().ok()).collect();
+                let mut by_id = self.db.get_chunk_embeddings(collection_id, &chunk_ids).await?;
+                results.iter()
+                    .map(|r| r.chunk_id.parse::<i64>().ok().and_then(|id| by_id.remove(&id)))
+                    .collect()
+            }
+            None => vec![None; results.len()],
+        };
+
+        let candidates = results.len();
+        let selected = select_diverse(results, &embeddings, request.mmr_lambda, request.max_chunks_per_document, limit);
+        println!("🎯 [多样化] 候选 {} 条，选出 {} 条（mmr_lambda: {:?}, 每文档上限: {:?}）",
+            candidates, selected.len(), request.mmr_lambda, request.max_chunks_per_document);
+        Ok(selected)
+    }
+
+    // 向量检索（含空结果时的降阈回退）
+    async fn vector_search(
+        &self,
+        request: &SearchRequest,
+        collection: &KnowledgeCollection,
+        limit: usize,
+        threshold: f32,
+    ) -> Result<Vec<SearchResult>> {
+        let model_id = collection.embedding_model.to_lowercase();
+
         // 生成查询向量（bge-large-zh 需要加官方查询指令前缀）
         let mut query_text = request.query.clone();
         if model_id.contains("bge-large-zh") {
@@ -436,6 +975,11 @@ impl KnowledgeSearchService {
             println!("🧩 [查询指令] 使用 bge-large-zh，已添加查询前缀");
         }
 
+        // 查询向量与集合中存储的向量使用相同的输出维度
+        let model = self.vector_service.get_embedding_model(&collection.embedding_model).await?;
+        let query_context = EmbeddingContext::query(&collection.id)
+            .with_dimensions(collection_output_dimensions(collection, &model));
+
         let query_embedding = if !request.api_key.is_empty() {
             println!("🔍 使用提供的API密钥生成查询向量，密钥长度: {}", request.api_key.len());
             
@@ -447,9 +991,7 @@ impl KnowledgeSearchService {
             // =================================================================
             
             // 直接使用vector_service的方法
-            let model = self.vector_service.get_embedding_model(&collection.embedding_model).await?;
-            let embeddings = self.vector_service.generate_embeddings_with_api_key_batch(&[query_text.clone()], &model, &request.api_key).await?;
-            embeddings.into_iter().next().unwrap_or_default()
+            self.vector_service.generate_query_embedding(&query_text, &model, &request.api_key, &query_context).await?
         } else {
             println!("🔍 API密钥为空，使用无密钥方式生成查询向量");
             
@@ -460,15 +1002,17 @@ impl KnowledgeSearchService {
             // ^^^^ 关键的调试日志 ^^^^
             // =================================================================
             
-            self.vector_service.generate_embedding(&query_text, &collection.embedding_model).await?
+            self.vector_service.generate_query_embedding(&query_text, &model, "", &query_context).await?
         };
 
         // 执行向量搜索
         let mut results = self.db.search_vectors(
             &query_embedding,
-            &collection_id,
+            &collection.id,
+            &collection.embedding_model,
             limit,
             threshold,
+            request.filters.as_ref(),
         ).await?;
 
         // 空结果自动降阈回退：先 0.40，再 0.30
@@ -479,52 +1023,95 @@ impl KnowledgeSearchService {
                     println!("🛠️ [回退] 初次检索无结果，降阈至 {:.2} 重试", rt);
                     results = self.db.search_vectors(
                         &query_embedding,
-                        &collection_id,
+                        &collection.id,
+                        &collection.embedding_model,
                         limit,
                         rt,
+                        request.filters.as_ref(),
                     ).await?;
                     if !results.is_empty() { break; }
                 }
             }
         }
 
-        // 记录搜索历史
-        if self.is_search_history_enabled().await? {
-            self.record_search_history(&request.query, &collection_id, results.len(), start_time.elapsed()).await?;
+        Ok(results)
+    }
+
+    // 多集合搜索
+    pub async fn search_all_collections(&self, request: SearchRequest) -> Result<Vec<SearchResponse>> {
+        let collection_ids = self.get_all_collections().await?.into_iter().map(|c| c.id).collect();
+        Ok(self.search_concurrently(request, collection_ids).await.0)
+    }
+
+    // 跨集合统一检索：并发搜索各集合，按集合内排名融合为一个全局排序的结果列表
+    pub async fn search_collections(&self, request: SearchRequest, collection_ids: Option<Vec<String>>) -> Result<SearchResponse> {
+        let start_time = std::time::Instant::now();
+        let limit = request.limit.unwrap_or(self.get_system_config().await?.search_limit);
+
+        let collection_ids = match collection_ids.filter(|ids| !ids.is_empty()) {
+            Some(ids) => ids,
+            None => self.get_all_collections().await?.into_iter().map(|c| c.id).collect(),
+        };
+
+        let (responses, failures) = self.search_concurrently(request, collection_ids.clone()).await;
+        if responses.is_empty() && !failures.is_empty() {
+            return Err(anyhow!("所有集合检索均失败: {}", failures.join("; ")));
         }
 
-        let query_time = start_time.elapsed();
-        let total_count = results.len();
+        let mut models: Vec<String> = Vec::new();
+        let mut results = Vec::new();
+        for mut response in responses {
+            if !models.contains(&response.embedding_model) {
+                models.push(response.embedding_model.clone());
+            }
+            rank_scores(&mut response.results);
+            results.extend(response.results);
+        }
+
+        results.sort_by(|a, b| {
+            b.normalized_score.partial_cmp(&a.normalized_score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.similarity.partial_cmp(&a.similarity).unwrap_or(std::cmp::Ordering::Equal))
+        });
+        results.truncate(limit);
 
         Ok(SearchResponse {
+            total_count: results.len(),
             results,
-            total_count,
-            query_time_ms: query_time.as_millis() as u64,
-            collection_id,
-            embedding_model: collection.embedding_model,
+            query_time_ms: start_time.elapsed().as_millis() as u64,
+            collection_id: collection_ids.join(","),
+            embedding_model: models.join(","),
         })
     }
 
-    // 多集合搜索
-    pub async fn search_all_collections(&self, request: SearchRequest) -> Result<Vec<SearchResponse>> {
-        let collections = self.get_all_collections().await?;
-        let mut responses = Vec::new();
-
-        for collection in collections {
+    // 并发搜索多个集合，成功的结果按传入顺序返回，失败的集合记录警告并返回错误信息
+    async fn search_concurrently(&self, request: SearchRequest, collection_ids: Vec<String>) -> (Vec<SearchResponse>, Vec<String>) {
+        let mut tasks = tokio::task::JoinSet::new();
+        for (position, collection_id) in collection_ids.into_iter().enumerate() {
+            let service = self.clone();
             let mut collection_request = request.clone();
-            collection_request.collection_id = Some(collection.id.clone());
+            collection_request.collection_id = Some(collection_id.clone());
+            tasks.spawn(async move { (position, collection_id, service.search(collection_request).await) });
+        }
 
-            match self.search(collection_request).await {
-                Ok(response) => {
-                    responses.push(response);
+        let mut responses = Vec::new();
+        let mut failures = Vec::new();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((position, _, Ok(response))) => responses.push((position, response)),
+                Ok((_, collection_id, Err(e))) => {
+                    warn!("Failed to search collection {}: {}", collection_id, e);
+                    failures.push(format!("{}: {}", collection_id, e));
                 }
                 Err(e) => {
-                    warn!("Failed to search collection {}: {}", collection.id, e);
+                    warn!("Collection search task failed: {}", e);
+                    failures.push(e.to_string());
                 }
             }
         }
 
-        Ok(responses)
+        responses.sort_by_key(|(position, _)| *position);
+        (responses.into_iter().map(|(_, response)| response).collect(), failures)
     }
 
     // 获取所有集合
@@ -535,7 +1122,7 @@ impl KnowledgeSearchService {
     // 获取集合
     async fn get_collection(&self, collection_id: &str) -> Result<KnowledgeCollection> {
         let row = sqlx::query(
-            "SELECT id, name, description, embedding_model, vector_dimensions, created_at, updated_at
+            "SELECT id, name, description, embedding_model, vector_dimensions, created_at, updated_at, distance_metric, chunking_strategy
              FROM knowledge_collections WHERE id = ?"
         )
         .bind(collection_id)
@@ -548,6 +1135,8 @@ impl KnowledgeSearchService {
             description: row.get(2),
             embedding_model: row.get(3),
             vector_dimensions: row.get(4),
+            distance_metric: DistanceMetric::parse(row.get(7)).unwrap_or_default(),
+            chunking_strategy: ChunkingStrategy::parse(row.get(8)).unwrap_or_default(),
             created_at: chrono::DateTime::from_timestamp(row.get(5), 0).unwrap_or_default(),
             updated_at: chrono::DateTime::from_timestamp(row.get(6), 0).unwrap_or_default(),
         })
@@ -579,6 +1168,14 @@ impl KnowledgeSearchService {
         Ok(config)
     }
 
+    // 检查是否启用持久化查询缓存
+    async fn is_query_cache_enabled(&self) -> Result<bool> {
+        match self.db.get_config("enable_query_cache").await? {
+            Some(value) => Ok(value.parse().unwrap_or(false)),
+            None => Ok(true),
+        }
+    }
+
     // 检查是否启用搜索历史
     async fn is_search_history_enabled(&self) -> Result<bool> {
         match self.db.get_config("enable_search_history").await? {
@@ -588,24 +1185,186 @@ impl KnowledgeSearchService {
     }
 
     // 记录搜索历史
-    async fn record_search_history(&self, query: &str, collection_id: &str, results_count: usize, execution_time: std::time::Duration) -> Result<()> {
+    async fn record_search_history(&self, request: &SearchRequest, collection_id: &str, results_count: usize, execution_time: std::time::Duration) -> Result<()> {
         let id = uuid::Uuid::new_v4().to_string();
+        let mut stored_request = without_api_keys(request);
+        stored_request.collection_id = Some(collection_id.to_string());
 
         sqlx::query(
-            "INSERT INTO search_history (id, query_text, collection_id, results_count, execution_time, created_at)
-             VALUES (?, ?, ?, ?, ?, ?)"
+            "INSERT INTO search_history (id, query_text, collection_id, results_count, execution_time, created_at, request)
+             VALUES (?, ?, ?, ?, ?, ?, ?)"
         )
         .bind(&id)
-        .bind(query)
+        .bind(&request.query)
         .bind(collection_id)
         .bind(results_count as i64)
         .bind(execution_time.as_millis() as i64)
         .bind(Utc::now().timestamp())
+        .bind(serde_json::to_string(&stored_request)?)
         .execute(self.db.knowledge_pool())
         .await?;
 
         Ok(())
     }
+
+    // 分页浏览搜索历史（按时间倒序），可按集合和查询文本（包含匹配，% 和 _ 按字面匹配）过滤
+    pub async fn list_search_history(
+        &self,
+        collection_id: Option<&str>,
+        query: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchHistoryPage> {
+        let pattern = query
+            .filter(|q| !q.trim().is_empty())
+            .map(|q| format!("%{}%", crate::database::escape_like(q.trim())));
+
+        let total: i64 = sqlx::query(
+            "SELECT COUNT(*) FROM search_history
+             WHERE (?1 IS NULL OR collection_id = ?1) AND (?2 IS NULL OR query_text LIKE ?2 ESCAPE '\\')"
+        )
+        .bind(collection_id)
+        .bind(&pattern)
+        .fetch_one(self.db.knowledge_pool())
+        .await?
+        .get(0);
+
+        let rows = sqlx::query(
+            "SELECT id, query_text, collection_id, results_count, execution_time, created_at, request
+             FROM search_history
+             WHERE (?1 IS NULL OR collection_id = ?1) AND (?2 IS NULL OR query_text LIKE ?2 ESCAPE '\\')
+             ORDER BY created_at DESC, rowid DESC
+             LIMIT ?3 OFFSET ?4"
+        )
+        .bind(collection_id)
+        .bind(&pattern)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(self.db.knowledge_pool())
+        .await?;
+
+        Ok(SearchHistoryPage {
+            items: rows.iter().map(search_history_from_row).collect(),
+            total: total as usize,
+            offset,
+            limit,
+        })
+    }
+
+    // 删除指定的搜索历史记录
+    pub async fn delete_search_history(&self, ids: &[String]) -> Result<usize> {
+        let mut deleted = 0;
+        for id in ids {
+            deleted += sqlx::query("DELETE FROM search_history WHERE id = ?")
+                .bind(id)
+                .execute(self.db.knowledge_pool())
+                .await?
+                .rows_affected() as usize;
+        }
+        Ok(deleted)
+    }
+
+    // 清空搜索历史，指定集合时只清空该集合的记录
+    pub async fn clear_search_history(&self, collection_id: Option<&str>) -> Result<usize> {
+        let result = sqlx::query("DELETE FROM search_history WHERE ?1 IS NULL OR collection_id = ?1")
+            .bind(collection_id)
+            .execute(self.db.knowledge_pool())
+            .await?;
+        Ok(result.rows_affected() as usize)
+    }
+
+    // 按历史记录的参数重新执行搜索；旧记录没有保存参数时只使用查询文本和集合
+    pub async fn replay_search(&self, history_id: &str, api_key: String) -> Result<SearchResponse> {
+        let row = sqlx::query(
+            "SELECT id, query_text, collection_id, results_count, execution_time, created_at, request
+             FROM search_history WHERE id = ?"
+        )
+        .bind(history_id)
+        .fetch_optional(self.db.knowledge_pool())
+        .await?
+        .ok_or_else(|| anyhow!("搜索历史 '{}' 不存在", history_id))?;
+
+        let history = search_history_from_row(&row);
+        let mut request = history.request.unwrap_or_else(|| SearchRequest {
+            query: history.query_text,
+            collection_id: history.collection_id,
+            limit: None,
+            threshold: None,
+            embedding_model: None,
+            api_key: String::new(),
+            filters: None,
+            mode: None,
+            hybrid_weight: None,
+            rerank: None,
+            mmr_lambda: None,
+            max_chunks_per_document: None,
+            context_chunks: None,
+            merge_adjacent: false,
+        });
+        request.api_key = api_key;
+
+        println!("🔁 [搜索历史] 重新执行查询: '{}'", request.query);
+        self.search(request).await
+    }
+
+    // 搜索分析：热门查询和没有结果的查询，since 为 Unix 时间戳（秒）
+    pub async fn search_analytics(&self, collection_id: Option<&str>, since: Option<i64>, limit: usize) -> Result<SearchAnalytics> {
+        let since = since.unwrap_or(0);
+
+        let totals = sqlx::query(
+            "SELECT COUNT(*), COALESCE(SUM(results_count = 0), 0) FROM search_history
+             WHERE (?1 IS NULL OR collection_id = ?1) AND created_at >= ?2"
+        )
+        .bind(collection_id)
+        .bind(since)
+        .fetch_one(self.db.knowledge_pool())
+        .await?;
+
+        let top_queries = self.query_stats(collection_id, since, "", "search_count DESC", limit).await?;
+        let zero_result_queries = self.query_stats(
+            collection_id, since, "HAVING zero_result_count > 0", "zero_result_count DESC", limit,
+        ).await?;
+
+        Ok(SearchAnalytics {
+            collection_id: collection_id.map(str::to_string),
+            total_searches: totals.get::<i64, _>(0) as usize,
+            zero_result_searches: totals.get::<i64, _>(1) as usize,
+            top_queries,
+            zero_result_queries,
+        })
+    }
+
+    // 按规范化的查询文本和集合聚合搜索历史
+    async fn query_stats(&self, collection_id: Option<&str>, since: i64, having: &str, order_by: &str, limit: usize) -> Result<Vec<QueryStats>> {
+        let sql = format!(
+            "SELECT MIN(trim(query_text)), collection_id, COUNT(*) AS search_count,
+                    SUM(results_count = 0) AS zero_result_count, AVG(results_count), AVG(execution_time), MAX(created_at)
+             FROM search_history
+             WHERE (?1 IS NULL OR collection_id = ?1) AND created_at >= ?2
+             GROUP BY lower(trim(query_text)), collection_id
+             {}
+             ORDER BY {}, MAX(created_at) DESC
+             LIMIT ?3",
+            having, order_by
+        );
+
+        let rows = sqlx::query(&sql)
+            .bind(collection_id)
+            .bind(since)
+            .bind(limit as i64)
+            .fetch_all(self.db.knowledge_pool())
+            .await?;
+
+        Ok(rows.iter().map(|row| QueryStats {
+            query_text: row.get(0),
+            collection_id: row.get(1),
+            search_count: row.get::<i64, _>(2) as usize,
+            zero_result_count: row.get::<i64, _>(3) as usize,
+            avg_results: row.get(4),
+            avg_execution_time_ms: row.get(5),
+            last_searched_at: chrono::DateTime::from_timestamp(row.get(6), 0).unwrap_or_default(),
+        }).collect())
+    }
 }
 
 // 知识库管理服务
@@ -632,16 +1391,18 @@ impl KnowledgeManagementService {
 
     // 删除集合
     pub async fn delete_collection(&self, collection_id: &str) -> Result<()> {
+        let vector_table = self.db.collection_vector_table(collection_id).await?;
         let mut tx = self.db.knowledge_pool().begin().await?;
 
         // 删除向量（通过关联表删除）
-        sqlx::query(
-            "DELETE FROM knowledge_vectors WHERE rowid IN (
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE rowid IN (
                 SELECT kc.id FROM knowledge_chunks kc
                 JOIN knowledge_documents kd ON kc.document_id = kd.id
                 WHERE kd.collection_id = ?
-            )"
-        )
+            )",
+            vector_table.name
+        ))
         .bind(collection_id)
         .execute(&mut *tx)
         .await?;
@@ -660,8 +1421,8 @@ impl KnowledgeManagementService {
 
         tx.commit().await?;
 
-        // 清理缓存
-        self.db.clear_cache();
+        // 清理该集合的查询缓存
+        self.db.invalidate_collection_cache(collection_id).await?;
 
         Ok(())
     }
@@ -686,16 +1447,7 @@ impl KnowledgeManagementService {
         .await?
         .get::<i64, _>(0) as usize;
 
-        let vectors_count = sqlx::query(
-            "SELECT COUNT(*) FROM knowledge_vectors kv
-             JOIN knowledge_chunks kc ON kv.rowid = kc.id
-             JOIN knowledge_documents kd ON kc.document_id = kd.id
-             WHERE kd.collection_id = ?"
-        )
-        .bind(collection_id)
-        .fetch_one(self.db.knowledge_pool())
-        .await?
-        .get::<i64, _>(0) as usize;
+        let vectors_count = self.db.get_vector_count(collection_id).await?;
 
         let total_size_bytes = sqlx::query(
             "SELECT SUM(CAST(LENGTH(kd.content) AS INTEGER)) FROM knowledge_documents kd WHERE kd.collection_id = ?"
@@ -733,10 +1485,13 @@ impl KnowledgeManagementService {
             .await?
             .get::<i64, _>(0) as usize;
 
-        let total_vectors = sqlx::query("SELECT COUNT(*) FROM knowledge_vectors")
-            .fetch_one(self.db.knowledge_pool())
-            .await?
-            .get::<i64, _>(0) as usize;
+        let mut total_vectors = 0;
+        for table in self.db.vector_tables().await? {
+            total_vectors += sqlx::query(&format!("SELECT COUNT(*) FROM {}", table))
+                .fetch_one(self.db.knowledge_pool())
+                .await?
+                .get::<i64, _>(0) as usize;
+        }
 
         let memory_usage = self.get_memory_usage().await?;
 
@@ -754,7 +1509,7 @@ impl KnowledgeManagementService {
     // 获取集合
     async fn get_collection(&self, collection_id: &str) -> Result<KnowledgeCollection> {
         let row = sqlx::query(
-            "SELECT id, name, description, embedding_model, vector_dimensions, created_at, updated_at
+            "SELECT id, name, description, embedding_model, vector_dimensions, created_at, updated_at, distance_metric, chunking_strategy
              FROM knowledge_collections WHERE id = ?"
         )
         .bind(collection_id)
@@ -767,6 +1522,8 @@ impl KnowledgeManagementService {
             description: row.get(2),
             embedding_model: row.get(3),
             vector_dimensions: row.get(4),
+            distance_metric: DistanceMetric::parse(row.get(7)).unwrap_or_default(),
+            chunking_strategy: ChunkingStrategy::parse(row.get(8)).unwrap_or_default(),
             created_at: chrono::DateTime::from_timestamp(row.get(5), 0).unwrap_or_default(),
             updated_at: chrono::DateTime::from_timestamp(row.get(6), 0).unwrap_or_default(),
         })
@@ -787,4 +1544,245 @@ impl KnowledgeManagementService {
 
         Ok(result.rows_affected() as usize)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(chunk_id: &str) -> SearchResult {
+        SearchResult {
+            chunk_id: chunk_id.to_string(),
+            chunk_text: String::new(),
+            document_id: "doc".to_string(),
+            document_title: String::new(),
+            file_name: None,
+            similarity: 0.0,
+            score: 0.0,
+            rerank_score: None,
+            chunk_index: 0,
+            context: None,
+            collection_id: String::new(),
+            normalized_score: None,
+            start_offset: None,
+            end_offset: None,
+            highlights: Vec::new(),
+            snippet: None,
+            breadcrumb: None,
+        }
+    }
+
+    async fn history_service() -> KnowledgeSearchService {
+        let db = Arc::new(DatabaseManager::in_memory().await.unwrap());
+        KnowledgeSearchService::new(db.clone(), Arc::new(VectorService::new(db)))
+    }
+
+    async fn record(service: &KnowledgeSearchService, query: &str, collection_id: &str, results_count: usize) {
+        let request: SearchRequest = serde_json::from_value(serde_json::json!({
+            "query": query, "collection_id": null, "limit": null, "threshold": null,
+            "embedding_model": null, "api_key": ""
+        })).unwrap();
+        service.record_search_history(&request, collection_id, results_count, std::time::Duration::from_millis(10)).await.unwrap();
+    }
+
+    #[test]
+    fn diversification_caps_documents_and_penalises_duplicates() {
+        let mut candidates = Vec::new();
+        for (chunk_id, document_id, score) in [("a1", "a", 0.9), ("a2", "a", 0.85), ("a3", "a", 0.8), ("b1", "b", 0.7)] {
+            let mut r = result(chunk_id);
+            r.document_id = document_id.to_string();
+            r.score = score;
+            candidates.push(r);
+        }
+        // a2 与 a1 几乎相同，a3 与 b1 方向各异
+        let embeddings = vec![
+            Some(vec![1.0, 0.0, 0.0]),
+            Some(vec![0.99, 0.1, 0.0]),
+            Some(vec![0.0, 1.0, 0.0]),
+            Some(vec![0.0, 0.0, 1.0]),
+        ];
+
+        let capped = select_diverse(candidates.clone(), &embeddings, None, Some(2), 3);
+        let ids: Vec<&str> = capped.iter().map(|r| r.chunk_id.as_str()).collect();
+        assert_eq!(ids, vec!["a1", "a2", "b1"]);
+
+        let mmr = select_diverse(candidates, &embeddings, Some(0.5), None, 2);
+        let ids: Vec<&str> = mmr.iter().map(|r| r.chunk_id.as_str()).collect();
+        assert_eq!(ids, vec!["a1", "a3"]);
+    }
+
+    #[test]
+    fn query_cache_key_ignores_api_key() {
+        let collection = KnowledgeCollection::new("docs".to_string(), "BAAI/bge-m3".to_string(), 1024);
+        let mut request: SearchRequest = serde_json::from_value(serde_json::json!({
+            "query": "如何配置", "collection_id": null, "limit": null, "threshold": null,
+            "embedding_model": null, "api_key": "sk-1"
+        })).unwrap();
+
+        let key = query_cache_key(&request, &collection, 10, 0.3).unwrap();
+        request.api_key = "sk-2".to_string();
+        assert_eq!(query_cache_key(&request, &collection, 10, 0.3).unwrap(), key);
+        assert_ne!(query_cache_key(&request, &collection, 5, 0.3).unwrap(), key);
+
+        request.query = "如何部署".to_string();
+        assert_ne!(query_cache_key(&request, &collection, 10, 0.3).unwrap(), key);
+    }
+
+    #[test]
+    fn ranks_scores_within_collection() {
+        let mut results = vec![result("a"), result("b"), result("c")];
+        results[0].score = 12.0;
+        results[1].score = 7.0;
+        results[2].score = 2.0;
+        results[2].rerank_score = Some(20.0);
+        rank_scores(&mut results);
+        let scores: Vec<f32> = results.iter().map(|r| r.normalized_score.unwrap()).collect();
+        assert_eq!(scores, vec![61.0 / 62.0, 61.0 / 63.0, 1.0]);
+
+        // 只看排名，与原始分数的量纲无关：BM25 分数和余弦相似度的同名次得分相同
+        let mut other = vec![result("d"), result("e")];
+        other[0].score = 0.9;
+        other[1].score = 0.1;
+        rank_scores(&mut other);
+        assert_eq!(other[0].normalized_score, results[2].normalized_score);
+        assert_eq!(other[1].normalized_score, results[0].normalized_score);
+    }
+
+    #[test]
+    fn joins_overlapping_chunks() {
+        let content = "第一句话。第二句话。第三句话。";
+        let chunks = vec![
+            KnowledgeChunk::new("doc".to_string(), 0, "第一句话。第二".to_string(), 1).with_offsets(0, 7),
+            KnowledgeChunk::new("doc".to_string(), 1, "第二句话。第三句话。".to_string(), 1).with_offsets(5, 15),
+        ];
+        assert_eq!(join_chunk_texts(&chunks), content);
+
+        // 旧分块没有偏移时按文本重叠去重
+        let legacy: Vec<KnowledgeChunk> = chunks.into_iter()
+            .map(|c| KnowledgeChunk::new(c.document_id, c.chunk_index, c.chunk_text, c.token_count))
+            .collect();
+        assert_eq!(join_chunk_texts(&legacy), content);
+    }
+
+    #[tokio::test]
+    async fn chunks_by_model_tokenizer_when_available() {
+        // 不访问网络：下载分词器会立即失败
+        std::env::set_var("HF_ENDPOINT", "http://127.0.0.1:9");
+
+        let db = Arc::new(DatabaseManager::in_memory().await.unwrap());
+        let vector_service = Arc::new(VectorService::new(db.clone()));
+        let processor = DocumentProcessor::new(db.clone(), vector_service.clone());
+        let collection = KnowledgeCollection::new("文档".to_string(), "bge-large-zh".to_string(), 1024);
+        db.create_collection(&collection).await.unwrap();
+        let model = vector_service.get_embedding_model("bge-large-zh").await.unwrap();
+        let max_tokens = model.max_tokens as usize;
+
+        // 没有分词器时按字符分块，块大小不超过 max_tokens；不能按 token 指定块大小
+        let content = "内存安全".repeat(max_tokens);
+        let chunks = processor
+            .preview_chunks(&content, Some(&collection.id), Some(max_tokens * 4), Some(0), ChunkSizeUnit::Chars, None)
+            .await
+            .unwrap();
+        assert!(chunks.len() >= 4);
+        assert!(chunks.iter().all(|chunk| chunk.text.chars().count() <= max_tokens));
+        let tokens = processor.preview_chunks(&content, Some(&collection.id), None, None, ChunkSizeUnit::Tokens, None).await;
+        assert!(tokens.is_err());
+        assert!(processor.preview_chunks(&content, None, None, None, ChunkSizeUnit::Tokens, None).await.is_err());
+
+        // 安装分词器后，默认按 token 分块并记录真实的 token 数
+        let vocab = ["[UNK]", "one", "two", "three", "four", "five", "."]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect();
+        let word_level = tokenizers::models::wordlevel::WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = tokenizers::Tokenizer::new(word_level);
+        tokenizer.with_pre_tokenizer(Some(tokenizers::pre_tokenizers::whitespace::Whitespace {}));
+        crate::chunking::install_tokenizer(&model, db.app_dir(), tokenizer.to_string(false).unwrap().as_bytes()).unwrap();
+
+        let content = "one two three four five. ".repeat(max_tokens);
+        let chunks = processor.preview_chunks(&content, Some(&collection.id), None, None, ChunkSizeUnit::Chars, None).await.unwrap();
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.token_count <= DEFAULT_CHUNK_TOKENS);
+            assert_eq!(chunk.token_count, tokenizer.encode(chunk.text.as_str(), false).unwrap().len());
+        }
+
+        // 显式按字符指定的块大小超过模型上限时，按 token 拆分
+        let chunks = processor
+            .preview_chunks(&content, Some(&collection.id), Some(content.chars().count()), Some(0), ChunkSizeUnit::Chars, None)
+            .await
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.token_count <= max_tokens));
+
+        let _ = std::fs::remove_dir_all(db.app_dir());
+    }
+
+    #[tokio::test]
+    async fn filters_search_history_literally() {
+        let service = history_service().await;
+        record(&service, "100% 覆盖率", "c1", 3).await;
+        record(&service, "1000 条记录", "c1", 3).await;
+        record(&service, "snake_case", "c1", 1).await;
+        record(&service, "snakeXcase", "c2", 1).await;
+
+        let page = service.list_search_history(None, Some("0%"), 0, 10).await.unwrap();
+        assert_eq!(page.items.iter().map(|h| h.query_text.as_str()).collect::<Vec<_>>(), ["100% 覆盖率"]);
+        let page = service.list_search_history(None, Some("e_c"), 0, 10).await.unwrap();
+        assert_eq!((page.total, page.items[0].query_text.as_str()), (1, "snake_case"));
+        let page = service.list_search_history(Some("c1"), None, 0, 2).await.unwrap();
+        assert_eq!((page.total, page.items.len()), (3, 2));
+    }
+
+    #[tokio::test]
+    async fn aggregates_search_analytics_by_normalized_query() {
+        let service = history_service().await;
+        record(&service, "Rust 所有权", "c1", 3).await;
+        record(&service, "  rust 所有权 ", "c1", 0).await;
+        record(&service, "rust 所有权", "c1", 3).await;
+        record(&service, "RUST 所有权", "c2", 2).await;
+        record(&service, "生命周期", "c1", 0).await;
+        record(&service, "生命周期", "c1", 0).await;
+        record(&service, "借用检查", "c1", 5).await;
+
+        let analytics = service.search_analytics(None, None, 10).await.unwrap();
+        assert_eq!((analytics.total_searches, analytics.zero_result_searches), (7, 3));
+
+        // 大小写和首尾空白不同的查询合并统计，不同集合分开统计
+        let top = &analytics.top_queries[0];
+        assert_eq!((top.query_text.to_lowercase().as_str(), top.collection_id.as_deref()), ("rust 所有权", Some("c1")));
+        assert_eq!((top.search_count, top.zero_result_count, top.avg_results), (3, 1, 2.0));
+        assert_eq!(analytics.top_queries.len(), 4);
+
+        // HAVING 只保留出现过零结果的查询，按零结果次数排序
+        let zero: Vec<(&str, usize)> = analytics.zero_result_queries.iter()
+            .map(|q| (q.query_text.as_str(), q.zero_result_count))
+            .collect();
+        assert_eq!(zero.len(), 2);
+        assert_eq!(zero[0], ("生命周期", 2));
+        assert_eq!(zero[1].1, 1);
+
+        let scoped = service.search_analytics(Some("c2"), None, 10).await.unwrap();
+        assert_eq!((scoped.total_searches, scoped.zero_result_searches, scoped.top_queries.len()), (1, 0, 1));
+        assert!(scoped.zero_result_queries.is_empty());
+
+        let future = service.search_analytics(None, Some(Utc::now().timestamp() + 60), 10).await.unwrap();
+        assert_eq!(future.total_searches, 0);
+    }
+
+    #[test]
+    fn rrf_fuses_weighted_ranks() {
+        let vector = vec![result("a"), result("b"), result("c")];
+        let keyword = vec![result("c"), result("d")];
+
+        // 关键词权重更高时，关键词第二名排在向量第一名之前
+        let fused = reciprocal_rank_fusion(vector, keyword, 0.3, 3);
+        let ids: Vec<&str> = fused.iter().map(|r| r.chunk_id.as_str()).collect();
+        assert_eq!(ids, vec!["c", "d", "a"]);
+    }
+}
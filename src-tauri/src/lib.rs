@@ -12,6 +12,14 @@ mod vector_service;
 mod knowledge_service;
 mod types;
 mod siliconflow_embedding;
+mod embedding_provider;
+mod openai_embedding;
+mod local_embedding;
+mod embedding_jobs;
+mod embedding_http;
+mod rerank;
+mod highlight;
+mod chunking;
 
 use database::DatabaseManager;
 use vector_service::VectorService;
@@ -86,6 +94,20 @@ pub fn run() {
       };
 
       let vector_service = Arc::new(VectorService::new(db.clone()));
+
+      // 注册 settings 中配置的 OpenAI 兼容嵌入提供方
+      match rt.block_on(openai_embedding::load_provider_configs(&db)) {
+        Ok(configs) => {
+          for config in configs {
+            let provider_id = config.id.clone();
+            match openai_embedding::OpenAiCompatibleProvider::new(config) {
+              Ok(provider) => vector_service.register_provider(Arc::new(provider)),
+              Err(e) => println!("⚠️ 嵌入提供方 {} 配置无效: {}", provider_id, e),
+            }
+          }
+        }
+        Err(e) => println!("⚠️ 读取嵌入提供方配置失败: {}", e),
+      }
       let document_processor = Arc::new(DocumentProcessor::new(db.clone(), vector_service.clone()));
       let search_service = Arc::new(KnowledgeSearchService::new(db.clone(), vector_service.clone()));
       let management_service = Arc::new(KnowledgeManagementService::new(db.clone(), vector_service.clone()));
@@ -169,12 +191,22 @@ pub fn run() {
       search_knowledge_base,
       search_knowledge_base_with_documents,
       search_all_collections,
+      search_collections,
+
+      // 搜索历史命令
+      get_search_history,
+      delete_search_history,
+      clear_search_history,
+      replay_search,
+      get_search_analytics,
 
       // 统计和管理命令
       get_knowledge_statistics,
       get_collection_stats,
       get_system_status,
       clear_cache,
+      clear_embedding_cache,
+      get_embedding_usage_summary,
       debug_database_info,
 
       // 嵌入模型命令
@@ -182,6 +214,14 @@ pub fn run() {
       generate_siliconflow_batch_embeddings_cmd,
       get_siliconflow_models_cmd,
       get_available_embedding_models,
+      get_embedding_providers,
+      set_embedding_model_provider,
+      save_embedding_provider_config,
+      get_embedding_provider_configs,
+      register_local_embedding_model,
+      install_model_tokenizer,
+      generate_gemma_batch_embeddings,
+      cancel_embedding_jobs,
 
       // 向量操作命令
       chunk_document_text,
@@ -223,13 +263,38 @@ async fn create_knowledge_collection(
     _description: Option<String>,
     embedding_model: Option<String>,
     vector_dimensions: Option<i32>,
+    distance_metric: Option<DistanceMetric>,
+    chunking_strategy: Option<ChunkingStrategy>,
     state: tauri::State<'_, AppState>,
 ) -> Result<String, String> {
-    let collection = KnowledgeCollection::new(
-        name,
-        embedding_model.unwrap_or_else(|| "bge-m3".to_string()),
-        vector_dimensions.unwrap_or(384),
-    );
+    let embedding_model = embedding_model.unwrap_or_else(|| "bge-m3".to_string());
+    let model = state.vector_service.get_embedding_model(&embedding_model).await
+        .map_err(|e| format!("获取嵌入模型失败: {}", e))?;
+
+    // 未指定维度时使用模型原生维度；指定的维度不能超过原生维度
+    let vector_dimensions = vector_dimensions.unwrap_or(model.dimensions);
+    if vector_dimensions <= 0 || vector_dimensions > model.dimensions {
+        return Err(format!(
+            "创建集合失败: 向量维度 {} 无效，模型 {} 支持的最大维度为 {}",
+            vector_dimensions, model.id, model.dimensions
+        ));
+    }
+    let supports_reduced = state.vector_service.supports_reduced_dimensions(&model)
+        .map_err(|e| format!("创建集合失败: {}", e))?;
+    if vector_dimensions < model.dimensions && !supports_reduced {
+        return Err(format!(
+            "创建集合失败: 模型 {} 不支持降维输出，只能使用 {} 维",
+            model.id, model.dimensions
+        ));
+    }
+
+    let mut collection = KnowledgeCollection::new(name, embedding_model, vector_dimensions);
+    if let Some(distance_metric) = distance_metric {
+        collection.distance_metric = distance_metric;
+    }
+    if let Some(chunking_strategy) = chunking_strategy {
+        collection.chunking_strategy = chunking_strategy;
+    }
 
     state.management_service.create_collection(collection).await
         .map(|_| "集合创建成功".to_string())
@@ -262,6 +327,14 @@ async fn search_knowledge_base(
     limit: Option<usize>,
     threshold: Option<f32>,
     api_key: String, // 改为必选参数来测试
+    filters: Option<SearchFilters>,
+    mode: Option<SearchMode>,
+    hybrid_weight: Option<f32>,
+    rerank: Option<RerankOptions>,
+    mmr_lambda: Option<f32>,
+    max_chunks_per_document: Option<usize>,
+    context_chunks: Option<usize>,
+    merge_adjacent: Option<bool>,
     state: tauri::State<'_, AppState>,
 ) -> Result<SearchResponse, String> {
     // 强制输出调试信息以确认函数被调用
@@ -303,6 +376,14 @@ async fn search_knowledge_base(
         threshold,
         embedding_model: None,
         api_key, // 现在是必选参数
+        filters,
+        mode,
+        hybrid_weight,
+        rerank,
+        mmr_lambda,
+        max_chunks_per_document,
+        context_chunks,
+        merge_adjacent: merge_adjacent.unwrap_or(false),
     };
 
     match state.search_service.search(request).await {
@@ -321,8 +402,15 @@ async fn search_knowledge_base_with_documents(
     document_ids: Option<Vec<String>>,
     limit: Option<usize>,
     threshold: Option<f32>,
+    filters: Option<SearchFilters>,
     state: tauri::State<'_, AppState>,
 ) -> Result<SearchResponse, String> {
+    // 文档ID作为过滤条件在检索时应用，而不是在取回 top-k 之后再过滤
+    let mut filters = filters.unwrap_or_default();
+    if document_ids.is_some() {
+        filters.document_ids = document_ids;
+    }
+
     let request = SearchRequest {
         query,
         collection_id: None, // 搜索所有集合
@@ -330,18 +418,18 @@ async fn search_knowledge_base_with_documents(
         threshold,
         embedding_model: None,
         api_key: String::new(), // 这个接口不需要API密钥，传递空字符串
+        filters: Some(filters),
+        mode: None,
+        hybrid_weight: None,
+        rerank: None,
+        mmr_lambda: None,
+        max_chunks_per_document: None,
+        context_chunks: None,
+        merge_adjacent: false,
     };
 
-    let mut response = state.search_service.search(request).await
-        .map_err(|e| format!("搜索知识库失败: {}", e))?;
-
-    // 如果指定了文档ID，过滤结果
-    if let Some(ids) = document_ids {
-        response.results.retain(|result| ids.contains(&result.document_id));
-    }
-
-    response.total_count = response.results.len();
-    Ok(response)
+    state.search_service.search(request).await
+        .map_err(|e| format!("搜索知识库失败: {}", e))
 }
 
 #[tauri::command]
@@ -358,12 +446,88 @@ async fn search_all_collections(
         threshold,
         embedding_model: None,
         api_key: String::new(), // 这个接口不需要API密钥，传递空字符串
+        filters: None,
+        mode: None,
+        hybrid_weight: None,
+        rerank: None,
+        mmr_lambda: None,
+        max_chunks_per_document: None,
+        context_chunks: None,
+        merge_adjacent: false,
     };
 
     state.search_service.search_all_collections(request).await
         .map_err(|e| format!("搜索所有集合失败: {}", e))
 }
 
+// 跨集合统一搜索：并发检索多个集合（未指定时为全部集合），按集合内排名融合后合并排序
+#[tauri::command]
+async fn search_collections(
+    request: SearchRequest,
+    collection_ids: Option<Vec<String>>,
+    state: tauri::State<'_, AppState>,
+) -> Result<SearchResponse, String> {
+    state.search_service.search_collections(request, collection_ids).await
+        .map_err(|e| format!("跨集合搜索失败: {}", e))
+}
+
+#[tauri::command]
+async fn get_search_history(
+    collection_id: Option<String>,
+    query: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Result<SearchHistoryPage, String> {
+    state.search_service
+        .list_search_history(collection_id.as_deref(), query.as_deref(), offset.unwrap_or(0), limit.unwrap_or(50))
+        .await
+        .map_err(|e| format!("获取搜索历史失败: {}", e))
+}
+
+#[tauri::command]
+async fn delete_search_history(
+    ids: Vec<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize, String> {
+    state.search_service.delete_search_history(&ids).await
+        .map_err(|e| format!("删除搜索历史失败: {}", e))
+}
+
+#[tauri::command]
+async fn clear_search_history(
+    collection_id: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize, String> {
+    let removed = state.search_service.clear_search_history(collection_id.as_deref()).await
+        .map_err(|e| format!("清空搜索历史失败: {}", e))?;
+    println!("🗑️ 已清空 {} 条搜索历史", removed);
+    Ok(removed)
+}
+
+// 按历史记录保存的参数重新执行搜索
+#[tauri::command]
+async fn replay_search(
+    history_id: String,
+    api_key: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<SearchResponse, String> {
+    state.search_service.replay_search(&history_id, api_key.unwrap_or_default()).await
+        .map_err(|e| format!("重新执行搜索失败: {}", e))
+}
+
+// 热门查询和无结果查询统计，since 为 Unix 时间戳（秒）
+#[tauri::command]
+async fn get_search_analytics(
+    collection_id: Option<String>,
+    since: Option<i64>,
+    limit: Option<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Result<SearchAnalytics, String> {
+    state.search_service.search_analytics(collection_id.as_deref(), since, limit.unwrap_or(20)).await
+        .map_err(|e| format!("获取搜索统计失败: {}", e))
+}
+
 #[tauri::command]
 async fn get_knowledge_documents(
     collection_id: String,
@@ -447,13 +611,154 @@ async fn get_available_embedding_models(state: tauri::State<'_, AppState>) -> Re
         .map_err(|e| format!("获取嵌入模型失败: {}", e))
 }
 
+#[tauri::command]
+async fn get_embedding_providers(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    Ok(state.vector_service.provider_ids())
+}
+
+#[tauri::command]
+async fn set_embedding_model_provider(
+    model_id: String,
+    provider: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    state.vector_service.set_model_provider(&model_id, &provider).await
+        .map(|_| "嵌入提供方已更新".to_string())
+        .map_err(|e| format!("更新嵌入提供方失败: {}", e))
+}
+
+#[tauri::command]
+async fn save_embedding_provider_config(
+    mut config: openai_embedding::OpenAiEmbeddingConfig,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    openai_embedding::restore_masked_api_key(&state.db, &mut config).await
+        .map_err(|e| format!("读取已保存的嵌入提供方配置失败: {}", e))?;
+
+    let provider = openai_embedding::OpenAiCompatibleProvider::new(config.clone())
+        .map_err(|e| format!("嵌入提供方配置无效: {}", e))?;
+
+    openai_embedding::save_provider_config(&state.db, &config).await
+        .map_err(|e| format!("保存嵌入提供方配置失败: {}", e))?;
+
+    state.vector_service.register_provider(Arc::new(provider));
+    Ok("嵌入提供方配置已保存".to_string())
+}
+
+#[tauri::command]
+async fn get_embedding_provider_configs(state: tauri::State<'_, AppState>) -> Result<Vec<openai_embedding::OpenAiEmbeddingConfig>, String> {
+    openai_embedding::load_provider_configs(&state.db).await
+        .map(|configs| configs.iter().map(|config| config.masked()).collect())
+        .map_err(|e| format!("获取嵌入提供方配置失败: {}", e))
+}
+
+// 注册本地离线嵌入模型（目录需包含 config.json、tokenizer.json、model.safetensors）
+#[tauri::command]
+async fn register_local_embedding_model(
+    name: String,
+    model_dir: String,
+    language: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<EmbeddingModel, String> {
+    let info = local_embedding::inspect_model_dir(std::path::Path::new(&model_dir))
+        .map_err(|e| format!("本地模型目录无效: {}", e))?;
+
+    let mut model = EmbeddingModel::new(
+        name,
+        model_dir,
+        info.dimensions as i32,
+        language.unwrap_or_else(|| "default".to_string()),
+        info.max_tokens as i32,
+    );
+    model.provider = local_embedding::LOCAL_PROVIDER_ID.to_string();
+
+    state.vector_service.register_embedding_model(&model).await
+        .map_err(|e| format!("注册本地嵌入模型失败: {}", e))?;
+
+    println!("✅ 已注册本地嵌入模型: {} ({} 维, 最大 {} tokens)", model.name, info.dimensions, info.max_tokens);
+    Ok(model)
+}
+
+// 安装嵌入模型的分词器（按 token 分块使用）：指定 source_path 时从本地文件复制，否则从 Hugging Face 下载
+#[tauri::command]
+async fn install_model_tokenizer(
+    model_id: String,
+    source_path: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    let model = state.vector_service.get_embedding_model(&model_id).await
+        .map_err(|e| format!("获取嵌入模型失败: {}", e))?;
+
+    let path = match source_path {
+        Some(source_path) => {
+            let bytes = std::fs::read(&source_path)
+                .map_err(|e| format!("读取分词器文件失败: {}", e))?;
+            chunking::install_tokenizer(&model, state.db.app_dir(), &bytes)
+        }
+        None => chunking::download_tokenizer(&model, state.db.app_dir()).await,
+    }
+    .map_err(|e| format!("安装分词器失败: {}", e))?;
+
+    println!("✅ 已安装模型 {} 的分词器: {:?}", model.name, path);
+    Ok(path.to_string_lossy().to_string())
+}
+
+// 本地模型批量嵌入（前端 embeddingService 的 Gemma 路径）
+#[tauri::command]
+async fn generate_gemma_batch_embeddings(
+    texts: Vec<String>,
+    model: String,
+    task_type: Option<String>,
+    dimensions: Option<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Vec<f32>>, String> {
+    println!("🔍 本地批量嵌入 - 模型: {}, 文本数量: {}, 任务类型: {:?}, 维度: {:?}", model, texts.len(), task_type, dimensions);
+
+    if texts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    state.vector_service.generate_local_embeddings(&texts, &model, dimensions).await
+        .map_err(|e| format!("本地嵌入生成失败: {}", e))
+}
+
+// 取消所有进行中的嵌入任务
+#[tauri::command]
+async fn cancel_embedding_jobs(state: tauri::State<'_, AppState>) -> Result<usize, String> {
+    let cancelled = state.vector_service.cancel_embedding_jobs();
+    println!("🛑 已取消 {} 个嵌入任务", cancelled);
+    Ok(cancelled)
+}
+
 #[tauri::command]
 async fn clear_cache(state: tauri::State<'_, AppState>) -> Result<String, String> {
-    state.db.clear_query_cache();
+    state.db.clear_query_cache().await
+        .map_err(|e| format!("清理查询缓存失败: {}", e))?;
     state.vector_service.clear_cache().await;
     Ok("缓存已清理".to_string())
 }
 
+#[tauri::command]
+async fn clear_embedding_cache(state: tauri::State<'_, AppState>) -> Result<u64, String> {
+    state.vector_service.clear_cache().await;
+    let removed = state.db.clear_embedding_cache().await
+        .map_err(|e| format!("清理嵌入缓存失败: {}", e))?;
+    println!("🗑️ 已清理 {} 条持久化嵌入缓存", removed);
+    Ok(removed)
+}
+
+#[tauri::command]
+async fn get_embedding_usage_summary(
+    group_by: UsageGroupBy,
+    collection_id: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<EmbeddingUsageSummary>, String> {
+    state.db.get_embedding_usage_summary(group_by, collection_id.as_deref(), since, until).await
+        .map_err(|e| format!("获取嵌入用量统计失败: {}", e))
+}
+
 #[tauri::command]
 async fn debug_database_info(state: tauri::State<'_, AppState>) -> Result<serde_json::Value, String> {
     use serde_json::json;
@@ -482,6 +787,8 @@ async fn debug_database_info(state: tauri::State<'_, AppState>) -> Result<serde_
             "description": collection.description,
             "embedding_model": collection.embedding_model,
             "vector_dimensions": collection.vector_dimensions,
+            "distance_metric": collection.distance_metric,
+            "chunking_strategy": collection.chunking_strategy,
             "document_count": documents.len(),
             "vector_count": vector_count,
             "documents": documents.iter().map(|doc| json!({
@@ -619,6 +926,14 @@ async fn search_knowledge_base_legacy(
         threshold: None,
         embedding_model: None,
         api_key: String::new(), // 传统接口不需要API密钥，传递空字符串
+        filters: None,
+        mode: None,
+        hybrid_weight: None,
+        rerank: None,
+        mmr_lambda: None,
+        max_chunks_per_document: None,
+        context_chunks: None,
+        merge_adjacent: false,
     };
 
     let response = state.search_service.search(request).await
@@ -712,6 +1027,7 @@ struct EmbeddingResponse {
     vectors_count: usize,
     chunks_count: usize,
     processing_time_ms: u64,
+    split_chunks: Vec<SplitChunkInfo>,
 }
 
 #[tauri::command]
@@ -817,6 +1133,7 @@ async fn generate_document_embeddings(
         metadata: Some(metadata),
         chunk_size: None,
         chunk_overlap: None,
+        chunk_size_unit: ChunkSizeUnit::default(),
     };
 
     println!("🚀 开始处理文档...");
@@ -834,43 +1151,26 @@ async fn generate_document_embeddings(
         vectors_count: response.vectors_count,
         chunks_count: response.chunks_count,
         processing_time_ms: response.processing_time_ms,
+        split_chunks: response.split_chunks,
     })
 }
 
+// 分块预览：与入库使用同一分块器，指定集合时采用集合的模型参数和分块策略
 #[tauri::command]
-async fn chunk_document_text(text: String, chunk_size: Option<usize>, chunk_overlap: Option<usize>) -> Result<Vec<String>, String> {
-    let chunk_size = chunk_size.unwrap_or(500);
-    let chunk_overlap = chunk_overlap.unwrap_or(50);
-
-    let mut chunks = Vec::new();
-    let mut start = 0;
-
-    while start < text.len() {
-        let end = std::cmp::min(start + chunk_size, text.len());
-        let mut chunk = text[start..end].to_string();
-
-        // 尝试在句子边界分割
-        if end < text.len() {
-            if let Some(last_sentence) = chunk.rfind('。') {
-                if last_sentence > chunk_size / 2 {
-                    chunk = chunk[..last_sentence + 1].to_string();
-                    start = start + last_sentence + 1 - chunk_overlap;
-                } else {
-                    start = end - chunk_overlap;
-                }
-            } else {
-                start = end - chunk_overlap;
-            }
-        } else {
-            start = end;
-        }
-
-        if !chunk.trim().is_empty() {
-            chunks.push(chunk.trim().to_string());
-        }
-    }
-
-    Ok(chunks)
+async fn chunk_document_text(
+    text: String,
+    chunk_size: Option<usize>,
+    chunk_overlap: Option<usize>,
+    chunk_size_unit: Option<ChunkSizeUnit>,
+    collection_id: Option<String>,
+    strategy: Option<ChunkingStrategy>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<chunking::TextChunk>, String> {
+    let unit = chunk_size_unit.unwrap_or_default();
+    state.document_processor
+        .preview_chunks(&text, collection_id.as_deref(), chunk_size, chunk_overlap, unit, strategy)
+        .await
+        .map_err(|e| format!("文档分块失败: {}", e))
 }
 
 // 重置知识库数据库
@@ -1091,6 +1391,7 @@ async fn get_database_stats(state: tauri::State<'_, AppState>) -> Result<serde_j
                 "knowledge_db": health.knowledge_db,
                 "vec_extension": health.vec_extension,
                 "cache_stats": health.cache_stats,
+                "query_cache": health.query_cache,
                 "storage_type": "sqlite-vec",
                 "description": "Tauri SQLite + sqlite-vec 系统",
                 "conversationCount": conversation_count,
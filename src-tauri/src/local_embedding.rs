@@ -0,0 +1,283 @@
+// 本地离线嵌入后端 - 使用 candle 在 CPU 上运行 bge 系列模型（BERT / XLM-RoBERTa）
+//
+// 模型目录需包含 config.json、tokenizer.json 和 model.safetensors，
+// 对应 EmbeddingModel 的 model_id 即为该目录路径。
+
+use crate::embedding_http::RateLimits;
+use crate::embedding_provider::{EmbeddingBatch, EmbeddingProvider};
+use crate::types::EmbeddingModel;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::{bert, xlm_roberta};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+pub const LOCAL_PROVIDER_ID: &str = "local";
+
+// CPU 推理时单批文本数量，过大会显著增加内存占用
+const LOCAL_MAX_BATCH: usize = 16;
+
+/// 本地模型目录信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalModelInfo {
+    pub model_type: String,
+    pub dimensions: usize,
+    pub max_tokens: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelConfigHeader {
+    #[serde(default)]
+    model_type: Option<String>,
+    hidden_size: usize,
+    max_position_embeddings: usize,
+    #[serde(default)]
+    pad_token_id: Option<u32>,
+}
+
+// 读取并校验模型目录，返回配置原文和基本信息
+fn read_model_dir(model_dir: &Path) -> Result<(String, ModelConfigHeader, LocalModelInfo)> {
+    for file in ["config.json", "tokenizer.json", "model.safetensors"] {
+        if !model_dir.join(file).is_file() {
+            return Err(anyhow!("本地模型目录 {:?} 缺少 {}", model_dir, file));
+        }
+    }
+
+    let config_json = std::fs::read_to_string(model_dir.join("config.json"))?;
+    let header: ModelConfigHeader = serde_json::from_str(&config_json)
+        .map_err(|e| anyhow!("解析 config.json 失败: {}", e))?;
+
+    let model_type = header.model_type.clone().unwrap_or_else(|| "bert".to_string());
+    let max_tokens = match model_type.as_str() {
+        // XLM-RoBERTa 的位置编码从 padding_idx + 1 开始，可用长度少 2
+        "xlm-roberta" => header.max_position_embeddings.saturating_sub(2),
+        "bert" => header.max_position_embeddings,
+        other => return Err(anyhow!("不支持的本地模型类型: {}", other)),
+    };
+
+    let info = LocalModelInfo {
+        model_type,
+        dimensions: header.hidden_size,
+        max_tokens,
+    };
+
+    Ok((config_json, header, info))
+}
+
+// 检查本地模型目录，注册模型前调用
+pub fn inspect_model_dir(model_dir: &Path) -> Result<LocalModelInfo> {
+    let (_, _, info) = read_model_dir(model_dir)?;
+    // 提前发现损坏的分词器，避免首次嵌入时才失败
+    Tokenizer::from_file(model_dir.join("tokenizer.json"))
+        .map_err(|e| anyhow!("加载 tokenizer.json 失败: {}", e))?;
+    Ok(info)
+}
+
+enum LocalEncoder {
+    Bert(bert::BertModel),
+    XlmRoberta(xlm_roberta::XLMRobertaModel),
+}
+
+// 已加载到内存的模型
+struct LoadedModel {
+    encoder: LocalEncoder,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+impl LoadedModel {
+    fn load(model_dir: &Path) -> Result<Self> {
+        let (config_json, header, info) = read_model_dir(model_dir)?;
+        println!("📦 加载本地嵌入模型: {:?} ({}, {} 维)", model_dir, info.model_type, info.dimensions);
+
+        let device = Device::Cpu;
+        let weights = model_dir.join("model.safetensors");
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], DType::F32, &device)? };
+
+        let encoder = if info.model_type == "xlm-roberta" {
+            let config: xlm_roberta::Config = serde_json::from_str(&config_json)?;
+            // bge-m3 导出的权重没有 "roberta." 前缀，其他导出方式可能带有
+            let model = xlm_roberta::XLMRobertaModel::new(&config, vb.clone())
+                .or_else(|_| xlm_roberta::XLMRobertaModel::new(&config, vb.pp("roberta")))?;
+            LocalEncoder::XlmRoberta(model)
+        } else {
+            let config: bert::Config = serde_json::from_str(&config_json)?;
+            LocalEncoder::Bert(bert::BertModel::load(vb, &config)?)
+        };
+
+        let mut tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json"))
+            .map_err(|e| anyhow!("加载 tokenizer.json 失败: {}", e))?;
+
+        let pad_id = header.pad_token_id.unwrap_or(0);
+        let pad_token = tokenizer.id_to_token(pad_id).unwrap_or_else(|| "[PAD]".to_string());
+        tokenizer.with_padding(Some(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            pad_id,
+            pad_token,
+            ..Default::default()
+        }));
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: info.max_tokens,
+                ..Default::default()
+            }))
+            .map_err(|e| anyhow!("设置截断参数失败: {}", e))?;
+
+        Ok(Self { encoder, tokenizer, device })
+    }
+
+    // 编码一批文本，使用 [CLS] 向量作为句向量（bge 系列的推荐池化方式）
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| anyhow!("文本分词失败: {}", e))?;
+
+        let mut input_ids = Vec::with_capacity(encodings.len());
+        let mut attention_mask = Vec::with_capacity(encodings.len());
+        let mut type_ids = Vec::with_capacity(encodings.len());
+        for encoding in &encodings {
+            input_ids.push(Tensor::new(encoding.get_ids(), &self.device)?);
+            attention_mask.push(Tensor::new(encoding.get_attention_mask(), &self.device)?);
+            type_ids.push(Tensor::new(encoding.get_type_ids(), &self.device)?);
+        }
+
+        let input_ids = Tensor::stack(&input_ids, 0)?;
+        let attention_mask = Tensor::stack(&attention_mask, 0)?;
+        let type_ids = Tensor::stack(&type_ids, 0)?;
+
+        let hidden_states = match &self.encoder {
+            LocalEncoder::Bert(model) => model.forward(&input_ids, &type_ids, Some(&attention_mask))?,
+            LocalEncoder::XlmRoberta(model) => {
+                model.forward(&input_ids, &attention_mask, &type_ids, None, None, None)?
+            }
+        };
+
+        let cls = hidden_states.get_on_dim(1, 0)?.to_dtype(DType::F32)?;
+        Ok(cls.to_vec2::<f32>()?)
+    }
+}
+
+// 本地嵌入提供方，按模型目录缓存已加载的模型
+pub struct LocalEmbeddingProvider {
+    models: Arc<Mutex<HashMap<PathBuf, Arc<LoadedModel>>>>,
+}
+
+impl LocalEmbeddingProvider {
+    pub fn new() -> Self {
+        Self {
+            models: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // 获取已加载的模型，首次使用时从磁盘加载（阻塞调用）
+    fn loaded_model(models: &Mutex<HashMap<PathBuf, Arc<LoadedModel>>>, model_dir: &Path) -> Result<Arc<LoadedModel>> {
+        let mut models = models.lock().unwrap();
+        if let Some(model) = models.get(model_dir) {
+            return Ok(model.clone());
+        }
+
+        let model = Arc::new(LoadedModel::load(model_dir)?);
+        models.insert(model_dir.to_path_buf(), model.clone());
+        Ok(model)
+    }
+}
+
+impl Default for LocalEmbeddingProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for LocalEmbeddingProvider {
+    fn name(&self) -> &str {
+        LOCAL_PROVIDER_ID
+    }
+
+    fn max_batch(&self) -> usize {
+        LOCAL_MAX_BATCH
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        // 本地推理已占满CPU，同一时间只跑一个批次
+        RateLimits {
+            max_concurrency: 1,
+            tokens_per_minute: None,
+        }
+    }
+
+    async fn embed_batch(&self, model: &EmbeddingModel, texts: &[String], _api_key: &str, _dimensions: Option<usize>) -> Result<EmbeddingBatch> {
+        let models = self.models.clone();
+        let model_dir = PathBuf::from(&model.model_id);
+        let texts = texts.to_vec();
+
+        // 模型推理是 CPU 密集型任务，放到阻塞线程池中执行
+        let embeddings = tokio::task::spawn_blocking(move || {
+            let loaded = Self::loaded_model(&models, &model_dir)?;
+            loaded.embed(&texts)
+        })
+        .await
+        .map_err(|e| anyhow!("本地嵌入任务异常退出: {}", e))??;
+
+        Ok(EmbeddingBatch { embeddings, usage: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokenizers::models::wordlevel::WordLevel;
+
+    fn temp_model_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("local-embedding-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_tokenizer(dir: &Path) {
+        let vocab = [("[UNK]".to_string(), 0u32)].into_iter().collect();
+        let model = WordLevel::builder().vocab(vocab).unk_token("[UNK]".to_string()).build().unwrap();
+        Tokenizer::new(model).save(dir.join("tokenizer.json"), false).unwrap();
+    }
+
+    fn error_message(dir: &Path) -> String {
+        inspect_model_dir(dir).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn inspects_model_dir_and_reports_broken_files() {
+        let dir = temp_model_dir();
+        std::fs::write(dir.join("model.safetensors"), b"").unwrap();
+
+        // 缺少文件
+        assert!(error_message(&dir).contains("config.json"));
+        std::fs::write(dir.join("config.json"), "{").unwrap();
+        assert!(error_message(&dir).contains("tokenizer.json"));
+
+        // 文件内容损坏
+        std::fs::write(dir.join("tokenizer.json"), "not a tokenizer").unwrap();
+        assert!(error_message(&dir).contains("解析 config.json 失败"));
+        std::fs::write(dir.join("config.json"), r#"{"hidden_size": 1024}"#).unwrap();
+        assert!(error_message(&dir).contains("解析 config.json 失败"));
+        std::fs::write(
+            dir.join("config.json"),
+            r#"{"model_type": "xlm-roberta", "hidden_size": 1024, "max_position_embeddings": 8194}"#,
+        ).unwrap();
+        assert!(error_message(&dir).contains("加载 tokenizer.json 失败"));
+
+        write_tokenizer(&dir);
+        let info = inspect_model_dir(&dir).unwrap();
+        assert_eq!((info.dimensions, info.max_tokens), (1024, 8192));
+
+        std::fs::write(dir.join("config.json"), r#"{"model_type": "gpt2", "hidden_size": 768, "max_position_embeddings": 1024}"#).unwrap();
+        assert!(error_message(&dir).contains("不支持的本地模型类型"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
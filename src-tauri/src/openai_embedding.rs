@@ -0,0 +1,372 @@
+// OpenAI 兼容的嵌入接口客户端（Ollama、vLLM、LM Studio、Azure、TEI 等）
+
+use crate::database::DatabaseManager;
+use crate::embedding_http::{EmbeddingError, HTTP_CLIENT, RateLimits, RetryPolicy, send_with_retry};
+use crate::embedding_provider::{EmbeddingBatch, EmbeddingProvider, TokenUsage, SILICONFLOW_PROVIDER_ID};
+use crate::local_embedding::LOCAL_PROVIDER_ID;
+use crate::types::EmbeddingModel;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// 提供方配置在 settings 表中的键前缀，完整键为 "embedding_provider:<id>"
+pub const PROVIDER_SETTING_PREFIX: &str = "embedding_provider:";
+
+// 内置提供方占用的ID，自定义配置不能使用
+const RESERVED_PROVIDER_IDS: &[&str] = &[SILICONFLOW_PROVIDER_ID, LOCAL_PROVIDER_ID];
+
+// 返回给前端的密钥用该前缀遮盖，只保留末尾 4 位
+const API_KEY_MASK: &str = "********";
+
+/// OpenAI 兼容嵌入服务配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiEmbeddingConfig {
+    pub id: String,
+    pub base_url: String,
+    #[serde(default = "default_path")]
+    pub path: String, // 相对 base_url 的路径，默认 "embeddings"
+    #[serde(default)]
+    pub query: HashMap<String, String>, // 例如 Azure 的 api-version
+    #[serde(default)]
+    pub api_key: Option<String>, // 未配置时使用调用方传入的密钥
+    #[serde(default = "default_auth_header")]
+    pub auth_header: String,
+    #[serde(default = "default_auth_scheme")]
+    pub auth_scheme: String, // 拼接在密钥前，Azure 的 api-key 头需设为空字符串
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    #[serde(default)]
+    pub model: Option<String>, // 覆盖 EmbeddingModel.model_id，例如 Ollama 的本地模型名
+    #[serde(default = "default_max_batch")]
+    pub max_batch: usize,
+    #[serde(default)]
+    pub encoding_format: Option<String>,
+    #[serde(default)]
+    pub max_concurrency: Option<usize>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    #[serde(default)]
+    pub supports_dimensions: bool, // 服务端支持 dimensions 参数（如 text-embedding-3 系列）
+}
+
+fn default_path() -> String {
+    "embeddings".to_string()
+}
+
+fn default_auth_header() -> String {
+    "Authorization".to_string()
+}
+
+fn default_auth_scheme() -> String {
+    "Bearer ".to_string()
+}
+
+fn default_max_batch() -> usize {
+    32
+}
+
+impl OpenAiEmbeddingConfig {
+    // 拼接完整的请求地址
+    pub fn endpoint(&self) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), self.path.trim_start_matches('/'))
+    }
+
+    // 遮盖密钥后的副本，用于返回给前端
+    pub fn masked(&self) -> Self {
+        let mut config = self.clone();
+        config.api_key = self.api_key.as_deref().filter(|k| !k.is_empty()).map(mask_api_key);
+        config
+    }
+}
+
+fn mask_api_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return API_KEY_MASK.to_string();
+    }
+    format!("{}{}", API_KEY_MASK, chars[chars.len() - 4..].iter().collect::<String>())
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiEmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding_format: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbeddingData>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiUsage {
+    #[serde(default)]
+    prompt_tokens: i64,
+    #[serde(default)]
+    total_tokens: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbeddingData {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: Option<usize>,
+}
+
+// OpenAI 兼容提供方
+pub struct OpenAiCompatibleProvider {
+    config: OpenAiEmbeddingConfig,
+}
+
+impl OpenAiCompatibleProvider {
+    pub fn new(config: OpenAiEmbeddingConfig) -> Result<Self> {
+        if config.id.trim().is_empty() {
+            return Err(anyhow!("嵌入提供方ID不能为空"));
+        }
+        if RESERVED_PROVIDER_IDS.contains(&config.id.as_str()) {
+            return Err(anyhow!("嵌入提供方ID {} 为内置提供方保留", config.id));
+        }
+        if config.base_url.trim().is_empty() {
+            return Err(anyhow!("嵌入提供方 {} 未配置 base_url", config.id));
+        }
+
+        Ok(Self { config })
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiCompatibleProvider {
+    fn name(&self) -> &str {
+        &self.config.id
+    }
+
+    fn max_batch(&self) -> usize {
+        self.config.max_batch.max(1)
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        let defaults = RateLimits::default();
+        RateLimits {
+            max_concurrency: self.config.max_concurrency.unwrap_or(defaults.max_concurrency),
+            tokens_per_minute: self.config.tokens_per_minute,
+        }
+    }
+
+    fn supports_dimensions(&self, _model: &EmbeddingModel) -> bool {
+        self.config.supports_dimensions
+    }
+
+    async fn embed_batch(&self, model: &EmbeddingModel, texts: &[String], api_key: &str, dimensions: Option<usize>) -> Result<EmbeddingBatch> {
+        let model_name = self.config.model.as_deref().unwrap_or(&model.model_id);
+        let url = self.config.endpoint();
+        println!("🔍 调用OpenAI兼容嵌入接口 - 提供方: {}, 地址: {}, 模型: {}, 文本数量: {}",
+            self.config.id, url, model_name, texts.len());
+
+        let request = OpenAiEmbeddingRequest {
+            model: model_name,
+            input: texts,
+            encoding_format: self.config.encoding_format.as_deref(),
+            dimensions,
+        };
+
+        // 本地服务通常不需要密钥，两者都为空时不发送认证头
+        let key = self.config.api_key.as_deref().filter(|k| !k.is_empty()).unwrap_or(api_key);
+
+        let mut policy = RetryPolicy::default();
+        if let Some(max_retries) = self.config.max_retries {
+            policy.max_retries = max_retries;
+        }
+
+        let response = send_with_retry(&self.config.id, &policy, || {
+            let mut builder = HTTP_CLIENT.post(&url).json(&request);
+            if !self.config.query.is_empty() {
+                builder = builder.query(&self.config.query);
+            }
+            if !key.is_empty() {
+                builder = builder.header(self.config.auth_header.as_str(), format!("{}{}", self.config.auth_scheme, key));
+            }
+            for (name, value) in &self.config.extra_headers {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+            builder
+        })
+        .await
+        .map_err(|e| {
+            println!("❌ {}", e);
+            e
+        })?;
+
+        let mut body: OpenAiEmbeddingResponse = response
+            .json()
+            .await
+            .map_err(|e| EmbeddingError::InvalidResponse { provider: self.config.id.clone(), message: e.to_string() })?;
+
+        // 部分服务不保证按输入顺序返回，按 index 重新排序
+        body.data.sort_by_key(|d| d.index.unwrap_or(usize::MAX));
+
+        Ok(EmbeddingBatch {
+            embeddings: body.data.into_iter().map(|d| d.embedding).collect(),
+            usage: body.usage.map(|u| TokenUsage {
+                prompt_tokens: u.prompt_tokens,
+                total_tokens: u.total_tokens,
+            }),
+        })
+    }
+}
+
+// 从 settings 表读取所有 OpenAI 兼容提供方配置
+pub async fn load_provider_configs(db: &DatabaseManager) -> Result<Vec<OpenAiEmbeddingConfig>> {
+    let mut configs = Vec::new();
+
+    for (key, value) in db.get_all_settings().await? {
+        if !key.starts_with(PROVIDER_SETTING_PREFIX) {
+            continue;
+        }
+
+        match serde_json::from_str::<OpenAiEmbeddingConfig>(&value) {
+            Ok(config) => configs.push(config),
+            Err(e) => println!("⚠️ 跳过无效的嵌入提供方配置 {}: {}", key, e),
+        }
+    }
+
+    Ok(configs)
+}
+
+// 前端回传的仍是遮盖后的密钥时，沿用已保存的密钥
+pub async fn restore_masked_api_key(db: &DatabaseManager, config: &mut OpenAiEmbeddingConfig) -> Result<()> {
+    if !config.api_key.as_deref().is_some_and(|k| k.starts_with(API_KEY_MASK)) {
+        return Ok(());
+    }
+
+    let key = format!("{}{}", PROVIDER_SETTING_PREFIX, config.id);
+    config.api_key = match db.get_setting(&key).await? {
+        Some(value) => serde_json::from_str::<OpenAiEmbeddingConfig>(&value)?.api_key,
+        None => None,
+    };
+    Ok(())
+}
+
+// 保存提供方配置到 settings 表
+pub async fn save_provider_config(db: &DatabaseManager, config: &OpenAiEmbeddingConfig) -> Result<()> {
+    let key = format!("{}{}", PROVIDER_SETTING_PREFIX, config.id);
+    let value = serde_json::to_string(config)?;
+    db.save_setting(&key, &value).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // 启动只响应一次的本地 HTTP 桩服务，返回服务地址和收到的原始请求
+    async fn stub_server(status: &'static str, body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status, body.len(), body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        (format!("http://{}", addr), handle)
+    }
+
+    fn test_model() -> EmbeddingModel {
+        EmbeddingModel::new("Test".to_string(), "test-embed".to_string(), 3, "default".to_string(), 512)
+    }
+
+    fn test_config(base_url: String) -> OpenAiEmbeddingConfig {
+        serde_json::from_value(serde_json::json!({ "id": "local-gateway", "base_url": base_url })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn embeds_against_stub_server() {
+        let (base_url, server) = stub_server(
+            "200 OK",
+            r#"{"data":[{"embedding":[0.0,1.0,0.0],"index":1},{"embedding":[1.0,0.0,0.0],"index":0}],"model":"m","usage":{"prompt_tokens":4,"total_tokens":4}}"#,
+        ).await;
+
+        let mut config = test_config(format!("{}/v1/", base_url));
+        config.model = Some("nomic-embed-text".to_string());
+        config.auth_header = "api-key".to_string();
+        config.auth_scheme = String::new();
+        config.extra_headers.insert("X-Team".to_string(), "kb".to_string());
+        let provider = OpenAiCompatibleProvider::new(config).unwrap();
+
+        let texts = vec!["first".to_string(), "second".to_string()];
+        let batch = provider.embed_batch(&test_model(), &texts, "secret", None).await.unwrap();
+        assert_eq!(batch.embeddings, vec![vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0]]);
+        assert_eq!(batch.usage.map(|u| u.total_tokens), Some(4));
+
+        let request = server.await.unwrap().to_lowercase();
+        assert!(request.starts_with("post /v1/embeddings "));
+        assert!(request.contains("api-key: secret"));
+        assert!(request.contains("x-team: kb"));
+        assert!(request.contains(r#""model":"nomic-embed-text""#));
+    }
+
+    #[test]
+    fn masks_api_keys_and_rejects_reserved_ids() {
+        let mut config = test_config("http://localhost:11434/v1".to_string());
+        config.api_key = Some("sk-1234567890abcd".to_string());
+        assert_eq!(config.masked().api_key.as_deref(), Some("********abcd"));
+        config.api_key = Some("short".to_string());
+        assert_eq!(config.masked().api_key.as_deref(), Some("********"));
+
+        for id in [SILICONFLOW_PROVIDER_ID, LOCAL_PROVIDER_ID] {
+            config.id = id.to_string();
+            assert!(OpenAiCompatibleProvider::new(config.clone()).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_error_status() {
+        let (base_url, server) = stub_server("500 Internal Server Error", r#"{"error":"boom"}"#).await;
+        let mut config = test_config(base_url);
+        config.max_retries = Some(0);
+        let provider = OpenAiCompatibleProvider::new(config).unwrap();
+
+        let result = provider.embed_batch(&test_model(), &["text".to_string()], "", None).await;
+        let error = result.unwrap_err();
+        assert!(matches!(error.downcast_ref::<EmbeddingError>(), Some(EmbeddingError::Http { status: 500, .. })));
+
+        // 未配置密钥时不发送认证头
+        let request = server.await.unwrap().to_lowercase();
+        assert!(!request.contains("authorization:"));
+    }
+}
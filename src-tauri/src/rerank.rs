@@ -0,0 +1,104 @@
+// 重排序（cross-encoder）客户端 - 硅基流动及 OpenAI 兼容的 /rerank 接口（Jina、TEI、vLLM 等）
+
+use crate::embedding_http::{EmbeddingError, HTTP_CLIENT, RetryPolicy, send_with_retry};
+use serde::{Deserialize, Serialize};
+
+pub const SILICONFLOW_RERANK_URL: &str = "https://api.siliconflow.cn/v1/rerank";
+pub const DEFAULT_RERANK_MODEL: &str = "BAAI/bge-reranker-v2-m3";
+
+const RERANK_PROVIDER: &str = "rerank";
+
+#[derive(Debug, Serialize)]
+struct RerankRequest<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [String],
+    top_n: usize,
+    return_documents: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResultItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResultItem {
+    index: usize,
+    relevance_score: f32,
+}
+
+// 对候选文本重排序，返回 (候选下标, 相关性分数)，按分数从高到低排列
+pub async fn rerank(url: &str, api_key: &str, model: &str, query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>, EmbeddingError> {
+    if documents.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    println!("🔁 调用重排序接口 - 地址: {}, 模型: {}, 候选数量: {}", url, model, documents.len());
+
+    let request = RerankRequest {
+        model,
+        query,
+        documents,
+        top_n: documents.len(),
+        return_documents: false,
+    };
+
+    let response = send_with_retry(RERANK_PROVIDER, &RetryPolicy::default(), || {
+        let builder = HTTP_CLIENT.post(url).json(&request);
+        if api_key.is_empty() {
+            builder
+        } else {
+            builder.header("Authorization", format!("Bearer {}", api_key))
+        }
+    })
+    .await?;
+
+    let body: RerankResponse = response
+        .json()
+        .await
+        .map_err(|e| EmbeddingError::InvalidResponse { provider: RERANK_PROVIDER.to_string(), message: e.to_string() })?;
+
+    let mut scores = Vec::with_capacity(body.results.len());
+    for item in body.results {
+        if item.index >= documents.len() {
+            return Err(EmbeddingError::InvalidResponse {
+                provider: RERANK_PROVIDER.to_string(),
+                message: format!("候选下标 {} 超出范围（共 {} 条）", item.index, documents.len()),
+            });
+        }
+        scores.push((item.index, item.relevance_score));
+    }
+
+    scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(scores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn reranks_against_stub_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/rerank", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let body = r#"{"id":"r","results":[{"index":0,"relevance_score":0.12},{"index":1,"relevance_score":0.93}]}"#;
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let documents = vec!["unrelated".to_string(), "answer".to_string()];
+        let scores = rerank(&url, "", DEFAULT_RERANK_MODEL, "question", &documents).await.unwrap();
+        assert_eq!(scores, vec![(1, 0.93), (0, 0.12)]);
+    }
+}
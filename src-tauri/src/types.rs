@@ -70,10 +70,93 @@ pub struct KnowledgeCollection {
     pub description: Option<String>,
     pub embedding_model: String,
     pub vector_dimensions: i32,
+    #[serde(default)]
+    pub distance_metric: DistanceMetric,
+    #[serde(default)]
+    pub chunking_strategy: ChunkingStrategy,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+// 集合的文档分块策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkingStrategy {
+    #[default]
+    Fixed,    // 固定长度窗口
+    Sentence, // 窗口尽量在句子边界结束
+    Markdown, // 按 Markdown 标题层级切分，代码块和表格保持完整
+}
+
+impl ChunkingStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChunkingStrategy::Fixed => "fixed",
+            ChunkingStrategy::Sentence => "sentence",
+            ChunkingStrategy::Markdown => "markdown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "fixed" => Some(ChunkingStrategy::Fixed),
+            "sentence" => Some(ChunkingStrategy::Sentence),
+            "markdown" => Some(ChunkingStrategy::Markdown),
+            _ => None,
+        }
+    }
+}
+
+// 分块大小的单位：前端传入的 chunk_size / chunk_overlap 按字符计，按 token 计时需要模型的 tokenizer.json
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkSizeUnit {
+    #[default]
+    Chars,
+    Tokens,
+}
+
+// 集合的向量距离度量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistanceMetric {
+    #[default]
+    Cosine,
+    L2,
+}
+
+impl DistanceMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DistanceMetric::Cosine => "cosine",
+            DistanceMetric::L2 => "l2",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "cosine" => Some(DistanceMetric::Cosine),
+            "l2" => Some(DistanceMetric::L2),
+            _ => None,
+        }
+    }
+
+    // vec0 表使用的 distance_metric
+    pub fn vec0_metric(&self) -> &'static str {
+        self.as_str()
+    }
+
+    // 将 vec0 返回的距离换算为相似度
+    pub fn similarity(&self, distance: f64) -> f32 {
+        match self {
+            // 余弦距离 = 1 - cos
+            DistanceMetric::Cosine => (1.0 - distance) as f32,
+            // 单位向量之间 L2² = 2 - 2cos
+            DistanceMetric::L2 => (1.0 - distance * distance / 2.0) as f32,
+        }
+    }
+}
+
 impl KnowledgeCollection {
     pub fn new(name: String, embedding_model: String, vector_dimensions: i32) -> Self {
         let now = Utc::now();
@@ -83,6 +166,8 @@ impl KnowledgeCollection {
             description: None,
             embedding_model,
             vector_dimensions,
+            distance_metric: DistanceMetric::default(),
+            chunking_strategy: ChunkingStrategy::default(),
             created_at: now,
             updated_at: now,
         }
@@ -139,9 +224,26 @@ pub struct KnowledgeChunk {
     pub chunk_index: i32,
     pub chunk_text: String,
     pub token_count: i32,
+    pub start_offset: Option<i64>, // 分块在文档内容中的字符偏移（旧数据为 None）
+    pub end_offset: Option<i64>,
+    #[serde(default)]
+    pub metadata: Option<String>, // JSON，见 ChunkMetadata
     pub created_at: DateTime<Utc>,
 }
 
+// 分块元数据
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breadcrumb: Option<String>, // 分块所在的标题路径，如 "Guide > Install > Linux"
+}
+
+impl ChunkMetadata {
+    pub fn parse(metadata: Option<&str>) -> Self {
+        metadata.and_then(|json| serde_json::from_str(json).ok()).unwrap_or_default()
+    }
+}
+
 impl KnowledgeChunk {
     pub fn new(document_id: String, chunk_index: i32, chunk_text: String, token_count: i32) -> Self {
         Self {
@@ -150,9 +252,18 @@ impl KnowledgeChunk {
             chunk_index,
             chunk_text,
             token_count,
+            start_offset: None,
+            end_offset: None,
+            metadata: None,
             created_at: Utc::now(),
         }
     }
+
+    pub fn with_offsets(mut self, start_offset: usize, end_offset: usize) -> Self {
+        self.start_offset = Some(start_offset as i64);
+        self.end_offset = Some(end_offset as i64);
+        self
+    }
 }
 
 // 向量嵌入
@@ -185,6 +296,52 @@ pub struct SearchResult {
     pub file_name: Option<String>,
     pub similarity: f32,
     pub score: f32,
+    #[serde(default)]
+    pub rerank_score: Option<f32>, // 重排序模型给出的相关性分数，未重排序时为 None
+    #[serde(default)]
+    pub chunk_index: i32,
+    #[serde(default)]
+    pub context: Option<ResultContext>,
+    #[serde(default)]
+    pub collection_id: String,
+    #[serde(default)]
+    pub normalized_score: Option<f32>, // 跨集合检索时按集合内排名融合的分数（0-1，各集合第一名为 1）
+    #[serde(default)]
+    pub start_offset: Option<i64>, // 分块在文档内容中的字符偏移，旧分块为 None
+    #[serde(default)]
+    pub end_offset: Option<i64>,
+    #[serde(default)]
+    pub highlights: Vec<MatchSpan>, // chunk_text 中命中的位置
+    #[serde(default)]
+    pub snippet: Option<Snippet>,
+    #[serde(default)]
+    pub breadcrumb: Option<String>, // 分块所在的标题路径（Markdown 分块）
+}
+
+// 命中位置（字符偏移，左闭右开）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+// 高亮摘要：chunk_text 中围绕命中的一段文本，highlights 相对 text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    pub text: String,
+    pub start: usize, // 摘要在 chunk_text 中的字符偏移
+    pub highlights: Vec<MatchSpan>,
+}
+
+// 命中分块周围的上下文段落
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultContext {
+    pub text: String,
+    pub start_chunk_index: i32,
+    pub end_chunk_index: i32,
+    pub start_offset: Option<i64>, // 段落在文档内容中的字符偏移，旧分块没有偏移时为 None
+    pub end_offset: Option<i64>,
+    pub matched_chunk_ids: Vec<String>, // 段落内命中的分块（合并相邻结果时可能有多个）
 }
 
 // 嵌入模型配置
@@ -197,10 +354,16 @@ pub struct EmbeddingModel {
     pub language: String,
     pub enabled: bool,
     pub max_tokens: i32,
+    #[serde(default = "default_embedding_provider")]
+    pub provider: String, // 嵌入服务提供方ID，对应已注册的 EmbeddingProvider
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+fn default_embedding_provider() -> String {
+    crate::embedding_provider::SILICONFLOW_PROVIDER_ID.to_string()
+}
+
 impl EmbeddingModel {
     pub fn new(
         name: String,
@@ -218,6 +381,7 @@ impl EmbeddingModel {
             language,
             enabled: true,
             max_tokens,
+            provider: default_embedding_provider(),
             created_at: now,
             updated_at: now,
         }
@@ -233,6 +397,64 @@ pub struct SearchRequest {
     pub threshold: Option<f32>,
     pub embedding_model: Option<String>,
     pub api_key: String, // 改为必选参数
+    #[serde(default)]
+    pub filters: Option<SearchFilters>,
+    #[serde(default)]
+    pub mode: Option<SearchMode>,
+    #[serde(default)]
+    pub hybrid_weight: Option<f32>, // 混合检索中向量排名的权重（0-1），其余为关键词排名
+    #[serde(default)]
+    pub rerank: Option<RerankOptions>,
+    #[serde(default)]
+    pub mmr_lambda: Option<f32>, // 提供时按最大边际相关（MMR）选取结果，1.0 只看相关性，越小越偏向多样性
+    #[serde(default)]
+    pub max_chunks_per_document: Option<usize>,
+    #[serde(default)]
+    pub context_chunks: Option<usize>, // 为每个结果附带前后各 N 个相邻分块
+    #[serde(default)]
+    pub merge_adjacent: bool,          // 把同一文档中相邻或重叠的结果合并为一个段落
+}
+
+// 重排序选项，提供时对检索候选做 cross-encoder 重排序
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RerankOptions {
+    pub model: Option<String>,     // 默认 BAAI/bge-reranker-v2-m3
+    pub url: Option<String>,       // 完整的 rerank 接口地址，默认硅基流动
+    pub api_key: Option<String>,   // 默认使用搜索请求的 api_key
+    pub candidates: Option<usize>, // 送入重排序的候选数量，默认为 limit 的3倍
+    pub min_score: Option<f32>,    // 低于该分数的结果被过滤
+}
+
+// 检索方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    #[default]
+    Vector,
+    Keyword,
+    Hybrid,
+}
+
+// 搜索过滤条件，在向量排序之前应用
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchFilters {
+    pub document_ids: Option<Vec<String>>,
+    pub mime_types: Option<Vec<String>>,
+    pub file_name_glob: Option<String>,           // SQLite GLOB 语法，如 "*.md"
+    pub metadata: Option<HashMap<String, String>>, // 文档元数据需全部匹配
+    pub created_after: Option<i64>,               // Unix 时间戳（秒），包含
+    pub created_before: Option<i64>,              // Unix 时间戳（秒），包含
+}
+
+impl SearchFilters {
+    pub fn is_empty(&self) -> bool {
+        self.document_ids.as_ref().map_or(true, |ids| ids.is_empty())
+            && self.mime_types.as_ref().map_or(true, |types| types.is_empty())
+            && self.file_name_glob.is_none()
+            && self.metadata.as_ref().map_or(true, |metadata| metadata.is_empty())
+            && self.created_after.is_none()
+            && self.created_before.is_none()
+    }
 }
 
 // 搜索响应
@@ -258,6 +480,8 @@ pub struct DocumentProcessRequest {
     pub metadata: Option<HashMap<String, String>>,
     pub chunk_size: Option<usize>,
     pub chunk_overlap: Option<usize>,
+    #[serde(default)]
+    pub chunk_size_unit: ChunkSizeUnit,
 }
 
 // 文档处理响应
@@ -267,6 +491,137 @@ pub struct DocumentProcessResponse {
     pub chunks_count: usize,
     pub vectors_count: usize,
     pub processing_time_ms: u64,
+    #[serde(default)]
+    pub split_chunks: Vec<SplitChunkInfo>, // 超出模型输入限制而被重新拆分的分块
+}
+
+// 被重新拆分的分块
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitChunkInfo {
+    pub original_index: usize, // 拆分前的分块序号
+    pub pieces: usize,         // 拆分后的片段数
+}
+
+// 生成嵌入后的文本片段（超长文本会对应多个片段）
+#[derive(Debug, Clone)]
+pub struct EmbeddedText {
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+// 嵌入缓存条目。超长文本拆分嵌入时按片段保存各自的向量，
+// split_offsets 为第二个片段起每个片段在原文中的起始字节位置
+#[derive(Debug, Clone, Default)]
+pub struct CachedEmbedding {
+    pub embeddings: Vec<Vec<f32>>,
+    pub split_offsets: Vec<usize>,
+}
+
+impl CachedEmbedding {
+    pub fn from_pieces(pieces: &[EmbeddedText]) -> Self {
+        let mut offset = 0;
+        let mut split_offsets = Vec::new();
+        for piece in &pieces[..pieces.len().saturating_sub(1)] {
+            offset += piece.text.len();
+            split_offsets.push(offset);
+        }
+        Self {
+            embeddings: pieces.iter().map(|p| p.embedding.clone()).collect(),
+            split_offsets,
+        }
+    }
+
+    // 按断点还原各片段，断点与文本对不上时返回 None（按未命中处理）
+    pub fn to_pieces(&self, text: &str) -> Option<Vec<EmbeddedText>> {
+        if self.embeddings.len() != self.split_offsets.len() + 1 {
+            return None;
+        }
+        let mut bounds = vec![0];
+        bounds.extend(&self.split_offsets);
+        bounds.push(text.len());
+        if bounds.windows(2).any(|w| w[0] > w[1]) || !bounds.iter().all(|&b| text.is_char_boundary(b)) {
+            return None;
+        }
+
+        Some(bounds.windows(2)
+            .zip(&self.embeddings)
+            .map(|(w, embedding)| EmbeddedText { text: text[w[0]..w[1]].to_string(), embedding: embedding.clone() })
+            .collect())
+    }
+
+    pub fn float_count(&self) -> usize {
+        self.embeddings.iter().map(Vec::len).sum()
+    }
+}
+
+// 嵌入调用的参数与归属信息（归属信息用于用量统计）
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddingContext {
+    pub operation: String, // ingest / query，为空时记为 other
+    pub collection_id: Option<String>,
+    pub document_id: Option<String>,
+    pub dimensions: Option<usize>, // 请求的输出维度，小于模型原生维度时生效
+}
+
+impl EmbeddingContext {
+    pub fn ingest(collection_id: &str, document_id: &str) -> Self {
+        Self {
+            operation: "ingest".to_string(),
+            collection_id: Some(collection_id.to_string()),
+            document_id: Some(document_id.to_string()),
+            dimensions: None,
+        }
+    }
+
+    pub fn query(collection_id: &str) -> Self {
+        Self {
+            operation: "query".to_string(),
+            collection_id: Some(collection_id.to_string()),
+            document_id: None,
+            dimensions: None,
+        }
+    }
+
+    pub fn with_dimensions(mut self, dimensions: Option<usize>) -> Self {
+        self.dimensions = dimensions;
+        self
+    }
+}
+
+// 单次嵌入调用的用量记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingUsageRecord {
+    pub provider: String,
+    pub model_id: String,
+    pub operation: String,
+    pub collection_id: Option<String>,
+    pub document_id: Option<String>,
+    pub text_count: i64,
+    pub prompt_tokens: i64,
+    pub total_tokens: i64,
+    pub estimated: bool, // 提供方未返回用量，token 数为估算值
+    pub latency_ms: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+// 用量汇总维度
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageGroupBy {
+    Day,
+    Collection,
+    Model,
+}
+
+// 用量汇总
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingUsageSummary {
+    pub key: String, // 日期 / 集合ID / 模型ID
+    pub calls: i64,
+    pub text_count: i64,
+    pub prompt_tokens: i64,
+    pub total_tokens: i64,
+    pub avg_latency_ms: f64,
 }
 
 // 数据库健康状态
@@ -276,6 +631,19 @@ pub struct DatabaseHealth {
     pub knowledge_db: bool,
     pub vec_extension: bool,
     pub cache_stats: (usize, usize),
+    pub query_cache: QueryCacheStats,
+}
+
+// 查询缓存统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryCacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub expired: u64,     // 因超过 TTL 被丢弃的条目数
+    pub invalidated: u64, // 因集合数据变更被清除的条目数
+    pub ttl_seconds: u64,
 }
 
 // 系统配置
@@ -375,6 +743,38 @@ pub struct SearchHistory {
     pub results_count: usize,
     pub execution_time_ms: u64,
     pub created_at: DateTime<Utc>,
+    pub request: Option<SearchRequest>, // 发起搜索时的完整参数（不含 API 密钥），旧记录为 None
+}
+
+// 搜索历史分页
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHistoryPage {
+    pub items: Vec<SearchHistory>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+// 按查询文本聚合的搜索统计（查询文本忽略大小写和首尾空白）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryStats {
+    pub query_text: String,
+    pub collection_id: Option<String>,
+    pub search_count: usize,
+    pub zero_result_count: usize,
+    pub avg_results: f64,
+    pub avg_execution_time_ms: f64,
+    pub last_searched_at: DateTime<Utc>,
+}
+
+// 搜索分析：热门查询和没有结果的查询
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchAnalytics {
+    pub collection_id: Option<String>,
+    pub total_searches: usize,
+    pub zero_result_searches: usize,
+    pub top_queries: Vec<QueryStats>,
+    pub zero_result_queries: Vec<QueryStats>,
 }
 
 // 系统状态
@@ -443,4 +843,19 @@ pub struct Message {
     pub timestamp: String,
     pub metadata: Option<String>,
     pub knowledge_references: Option<Vec<KnowledgeReference>>,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_distances_to_similarity() {
+        // 相同、正交、相反的单位向量：余弦距离 0/1/2，L2 距离 0/√2/2
+        for (cosine_distance, l2_distance, expected) in [(0.0, 0.0, 1.0), (1.0, 2f64.sqrt(), 0.0), (2.0, 2.0, -1.0)] {
+            assert!((DistanceMetric::Cosine.similarity(cosine_distance) - expected).abs() < 1e-6);
+            assert!((DistanceMetric::L2.similarity(l2_distance) - expected).abs() < 1e-6);
+        }
+
+        assert_eq!(DistanceMetric::default(), DistanceMetric::Cosine);
+    }
+}
@@ -1,13 +1,21 @@
 use crate::database::DatabaseManager;
+use crate::embedding_http::{self, EmbeddingError, ProviderLimiter};
+use crate::embedding_jobs::{EmbeddingCancelled, EmbeddingJobGuard, EmbeddingJobRegistry};
+use crate::embedding_provider::{EmbeddingBatch, EmbeddingProvider, EmbeddingProviderRegistry, TokenUsage};
+use crate::local_embedding::{self, LOCAL_PROVIDER_ID};
 use crate::types::*;
 use anyhow::{Result, anyhow};
 use tracing::info;
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use lru::LruCache;
 use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 use sqlx::Row;
+use unicode_segmentation::UnicodeSegmentation;
 
 // L2归一化函数
 fn normalize_vector(mut vector: Vec<f32>) -> Vec<f32> {
@@ -20,22 +28,227 @@ fn normalize_vector(mut vector: Vec<f32>) -> Vec<f32> {
     vector
 }
 
+// 拆分重试的最大递归深度，每层文本长度减半
+const MAX_SPLIT_DEPTH: usize = 24;
+
+type EmbedFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Vec<EmbeddedText>>>> + Send + 'a>>;
+
+// 在文本中部附近寻找断点（优先句末标点，其次空白），拆成两段
+fn split_text_near_middle(text: &str) -> Option<(String, String)> {
+    let graphemes: Vec<(usize, &str)> = text.grapheme_indices(true).collect();
+    if graphemes.len() < 2 {
+        return None;
+    }
+
+    let mid = graphemes.len() / 2;
+    let window = graphemes.len() / 4;
+    // 断点 i 表示在第 i 个字素之前切分
+    let find_break = |is_break: &dyn Fn(&str) -> bool| {
+        (0..=window)
+            .flat_map(|offset| [mid + offset, mid.saturating_sub(offset)])
+            .find(|&i| i > 0 && i < graphemes.len() && is_break(graphemes[i - 1].1))
+    };
+
+    let split_at = find_break(&|g| matches!(g, "。" | "！" | "？" | "；" | "." | "!" | "?" | ";" | "\n"))
+        .or_else(|| find_break(&|g| g.trim().is_empty()))
+        .unwrap_or(mid);
+    let byte_index = graphemes[split_at].0;
+
+    Some((text[..byte_index].to_string(), text[byte_index..].to_string()))
+}
+
+// 合并同一文本的多个片段向量（取平均后重新归一化）
+fn merge_pieces(pieces: Vec<EmbeddedText>) -> Vec<f32> {
+    if pieces.len() == 1 {
+        return pieces.into_iter().next().map(|p| p.embedding).unwrap_or_default();
+    }
+
+    let dimensions = pieces.first().map_or(0, |p| p.embedding.len());
+    let mut merged = vec![0.0; dimensions];
+    for piece in &pieces {
+        for (m, v) in merged.iter_mut().zip(&piece.embedding) {
+            *m += v;
+        }
+    }
+    normalize_vector(merged)
+}
+
+// 嵌入缓存中的模型标识，带上提供方以区分不同后端的同名模型，降维输出另带维度后缀
+fn embedding_cache_model_key(model: &EmbeddingModel, dimensions: Option<usize>) -> String {
+    match dimensions.filter(|d| *d < model.dimensions as usize) {
+        Some(dimensions) => format!("{}/{}@{}", model.provider, model.model_id, dimensions),
+        None => format!("{}/{}", model.provider, model.model_id),
+    }
+}
+
+// 内存嵌入缓存的容量：条目数和向量总大小（f32 个数，约 64MB）两个上限
+const EMBEDDING_CACHE_MAX_ENTRIES: usize = 10000;
+const EMBEDDING_CACHE_MAX_FLOATS: usize = 16 * 1024 * 1024;
+
+// 内存嵌入缓存，超出任一上限时淘汰最久未使用的条目
+struct EmbeddingMemoryCache {
+    entries: LruCache<String, CachedEmbedding>,
+    floats: usize,
+    max_floats: usize,
+}
+
+impl EmbeddingMemoryCache {
+    fn new(max_entries: usize, max_floats: usize) -> Self {
+        Self {
+            entries: LruCache::new(std::num::NonZeroUsize::new(max_entries.max(1)).unwrap()),
+            floats: 0,
+            max_floats,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<&CachedEmbedding> {
+        self.entries.get(key)
+    }
+
+    fn put(&mut self, key: String, entry: CachedEmbedding) {
+        let size = entry.float_count();
+        if size > self.max_floats {
+            return;
+        }
+        if let Some((_, evicted)) = self.entries.push(key, entry) {
+            self.floats -= evicted.float_count();
+        }
+        self.floats += size;
+        while self.floats > self.max_floats {
+            match self.entries.pop_lru() {
+                Some((_, evicted)) => self.floats -= evicted.float_count(),
+                None => break,
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.floats = 0;
+    }
+}
+
+// 文本内容哈希，作为嵌入缓存键
+fn text_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
 // 向量服务
 pub struct VectorService {
     db: Arc<DatabaseManager>,
-    embedding_cache: Arc<RwLock<LruCache<String, Vec<f32>>>>,
+    embedding_cache: Arc<RwLock<EmbeddingMemoryCache>>,
     model_cache: Arc<RwLock<HashMap<String, EmbeddingModel>>>,
+    providers: Arc<EmbeddingProviderRegistry>,
+    jobs: Arc<EmbeddingJobRegistry>,
+    limiters: Arc<std::sync::Mutex<HashMap<String, Arc<ProviderLimiter>>>>,
 }
 
+// 从 embedding_models 查询行构造模型配置（列顺序见 EMBEDDING_MODEL_COLUMNS）
+fn embedding_model_from_row(row: &sqlx::sqlite::SqliteRow) -> EmbeddingModel {
+    EmbeddingModel {
+        id: row.get(0),
+        name: row.get(1),
+        model_id: row.get(2),
+        dimensions: row.get(3),
+        language: row.get(4),
+        enabled: row.get(5),
+        max_tokens: row.get(6),
+        created_at: chrono::DateTime::from_timestamp(row.get(7), 0).unwrap_or_default(),
+        updated_at: chrono::DateTime::from_timestamp(row.get(8), 0).unwrap_or_default(),
+        provider: row.get(9),
+    }
+}
+
+const EMBEDDING_MODEL_COLUMNS: &str =
+    "id, name, model_id, dimensions, language, enabled, max_tokens, created_at, updated_at, provider";
+
 impl VectorService {
     pub fn new(db: Arc<DatabaseManager>) -> Self {
         Self {
             db,
-            embedding_cache: Arc::new(RwLock::new(LruCache::new(std::num::NonZeroUsize::new(10000).unwrap()))),
+            embedding_cache: Arc::new(RwLock::new(EmbeddingMemoryCache::new(EMBEDDING_CACHE_MAX_ENTRIES, EMBEDDING_CACHE_MAX_FLOATS))),
             model_cache: Arc::new(RwLock::new(HashMap::new())),
+            providers: Arc::new(EmbeddingProviderRegistry::new()),
+            jobs: Arc::new(EmbeddingJobRegistry::new()),
+            limiters: Arc::new(std::sync::Mutex::new(HashMap::new())),
         }
     }
 
+    // 取消所有进行中的嵌入任务
+    pub fn cancel_embedding_jobs(&self) -> usize {
+        self.jobs.cancel_all()
+    }
+
+    // 注册嵌入提供方
+    pub fn register_provider(&self, provider: Arc<dyn EmbeddingProvider>) {
+        // 配置可能变化，丢弃旧的限流器，下次调用时按新配置重建
+        self.limiters.lock().unwrap().remove(provider.name());
+        self.providers.register(provider);
+    }
+
+    // 获取提供方的限流器，同一提供方的所有请求共享并发和token配额
+    fn limiter_for(&self, provider: &dyn EmbeddingProvider) -> Arc<ProviderLimiter> {
+        let mut limiters = self.limiters.lock().unwrap();
+        limiters
+            .entry(provider.name().to_string())
+            .or_insert_with(|| Arc::new(ProviderLimiter::new(provider.rate_limits())))
+            .clone()
+    }
+
+    // 已注册的嵌入提供方ID
+    pub fn provider_ids(&self) -> Vec<String> {
+        self.providers.provider_ids()
+    }
+
+    // 新增或更新嵌入模型配置
+    pub async fn register_embedding_model(&self, model: &EmbeddingModel) -> Result<()> {
+        self.providers.get(&model.provider)?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO embedding_models (id, name, model_id, dimensions, language, enabled, max_tokens, created_at, updated_at, provider)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&model.id)
+        .bind(&model.name)
+        .bind(&model.model_id)
+        .bind(model.dimensions)
+        .bind(&model.language)
+        .bind(model.enabled)
+        .bind(model.max_tokens)
+        .bind(model.created_at.timestamp())
+        .bind(model.updated_at.timestamp())
+        .bind(&model.provider)
+        .execute(self.db.main_pool())
+        .await?;
+
+        self.model_cache.write().await.clear();
+        Ok(())
+    }
+
+    // 修改模型使用的嵌入提供方
+    pub async fn set_model_provider(&self, model_id: &str, provider_id: &str) -> Result<()> {
+        // 先确认提供方已注册，避免写入无法使用的配置
+        self.providers.get(provider_id)?;
+
+        let result = sqlx::query(
+            "UPDATE embedding_models SET provider = ?, updated_at = ? WHERE model_id = ? OR id = ?"
+        )
+        .bind(provider_id)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(model_id)
+        .bind(model_id)
+        .execute(self.db.main_pool())
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("嵌入模型不存在: {}", model_id));
+        }
+
+        // 模型配置已变化，清理模型缓存
+        self.model_cache.write().await.clear();
+        Ok(())
+    }
+
     // 获取嵌入模型配置
     pub async fn get_embedding_model(&self, model_id: &str) -> Result<EmbeddingModel> {
         // 检查缓存
@@ -47,25 +260,18 @@ impl VectorService {
         }
 
         // 从数据库获取 - 尝试通过model_id查找，如果找不到则通过id查找
-        let row = sqlx::query(
-            "SELECT id, name, model_id, dimensions, language, enabled, max_tokens, created_at, updated_at
-             FROM embedding_models WHERE model_id = ? AND enabled = 1"
-        )
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM embedding_models WHERE (model_id = ? OR id = ?) AND enabled = 1
+             ORDER BY model_id = ? DESC LIMIT 1",
+            EMBEDDING_MODEL_COLUMNS
+        ))
+        .bind(model_id)
+        .bind(model_id)
         .bind(model_id)
         .fetch_one(self.db.main_pool())
         .await?;
 
-        let model = EmbeddingModel {
-            id: row.get(0),
-            name: row.get(1),
-            model_id: row.get(2),
-            dimensions: row.get(3),
-            language: row.get(4),
-            enabled: row.get(5),
-            max_tokens: row.get(6),
-            created_at: chrono::DateTime::from_timestamp(row.get(7), 0).unwrap_or_default(),
-            updated_at: chrono::DateTime::from_timestamp(row.get(8), 0).unwrap_or_default(),
-        };
+        let model = embedding_model_from_row(&row);
 
         // 更新缓存
         {
@@ -76,114 +282,319 @@ impl VectorService {
         Ok(model)
     }
 
+    // 模型是否支持降维输出（由提供方判断）
+    pub fn supports_reduced_dimensions(&self, model: &EmbeddingModel) -> Result<bool> {
+        Ok(self.providers.get(&model.provider)?.supports_dimensions(model))
+    }
+
     // 获取所有可用的嵌入模型
     pub async fn get_available_models(&self) -> Result<Vec<EmbeddingModel>> {
-        let rows = sqlx::query(
-            "SELECT id, name, model_id, dimensions, language, enabled, max_tokens, created_at, updated_at
-             FROM embedding_models WHERE enabled = 1 ORDER BY language, name"
-        )
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM embedding_models WHERE enabled = 1 ORDER BY language, name",
+            EMBEDDING_MODEL_COLUMNS
+        ))
         .fetch_all(self.db.main_pool())
         .await?;
 
-        let mut models = Vec::new();
-        for row in rows {
-            models.push(EmbeddingModel {
-                id: row.get(0),
-                name: row.get(1),
-                model_id: row.get(2),
-                dimensions: row.get(3),
-                language: row.get(4),
-                enabled: row.get(5),
-                max_tokens: row.get(6),
-                created_at: chrono::DateTime::from_timestamp(row.get(7), 0).unwrap_or_default(),
-                updated_at: chrono::DateTime::from_timestamp(row.get(8), 0).unwrap_or_default(),
-            });
-        }
-
-        Ok(models)
+        Ok(rows.iter().map(embedding_model_from_row).collect())
     }
 
     // 生成文本嵌入
     pub async fn generate_embedding(&self, text: &str, model_id: &str) -> Result<Vec<f32>> {
-        // 生成缓存键
-        let cache_key = format!("embedding:{}:{}", model_id, text);
-
-        // 检查缓存
-        {
-            let mut cache = self.embedding_cache.write().await;
-            if let Some(cached_embedding) = cache.get(&cache_key) {
-                return Ok(cached_embedding.clone());
-            }
-        }
-
         // 获取模型配置
         let model = self.get_embedding_model(model_id).await?;
 
-        // 调用实际的嵌入服务（使用空API密钥，将返回错误而不是回退到模拟实现）
-        let embedding = self.generate_real_embedding(text, &model, "").await?;
-
-        // 缓存结果
-        {
-            let mut cache = self.embedding_cache.write().await;
-            cache.put(cache_key, embedding.clone());
-        }
-
-        Ok(embedding)
+        // 调用实际的嵌入服务（使用空API密钥，将返回错误而不是回退到模拟实现），缓存由批量路径统一处理
+        self.generate_real_embedding(text, &model, "").await
     }
 
     // 批量生成嵌入（使用API密钥）
     pub async fn generate_embeddings_with_api_key_batch(&self, texts: &[String], model: &EmbeddingModel, api_key: &str) -> Result<Vec<Vec<f32>>> {
         // 调用实际的嵌入服务，使用提供的API密钥
-        let embeddings = self.generate_real_embeddings_batch(texts, model, api_key).await?;
+        let embeddings = self.generate_real_embeddings_batch(texts, model, api_key, &EmbeddingContext::default()).await?;
         Ok(embeddings)
     }
 
+    // 批量生成嵌入（使用API密钥），超长文本拆分后的片段单独返回，供文档入库时拆成多个分块
+    pub async fn generate_split_embeddings_with_api_key(&self, texts: &[String], model: &EmbeddingModel, api_key: &str, context: &EmbeddingContext) -> Result<Vec<Vec<EmbeddedText>>> {
+        self.generate_real_embeddings_split(texts, model, api_key, context).await
+    }
+
+    // 生成查询向量（context 携带所查询的集合及其向量维度）
+    pub async fn generate_query_embedding(&self, text: &str, model: &EmbeddingModel, api_key: &str, context: &EmbeddingContext) -> Result<Vec<f32>> {
+        let embeddings = self.generate_real_embeddings_batch(&[text.to_string()], model, api_key, context).await?;
+        embeddings.into_iter().next().ok_or_else(|| anyhow!("嵌入提供方返回空结果"))
+    }
+
     // 批量生成嵌入
     pub async fn generate_embeddings_batch(&self, texts: &[String], model_id: &str) -> Result<Vec<Vec<f32>>> {
         let model = self.get_embedding_model(model_id).await?;
 
         // 调用实际的嵌入服务（使用空API密钥，将回退到模拟实现）
-        let embeddings = self.generate_real_embeddings_batch(texts, &model, "").await?;
+        let embeddings = self.generate_real_embeddings_batch(texts, &model, "", &EmbeddingContext::default()).await?;
 
         Ok(embeddings)
     }
 
+    // 使用本地模型批量生成嵌入（generate_gemma_batch_embeddings 命令）
+    // model 可以是已注册本地模型的 id / model_id / name，也可以直接是模型目录。
+    // dimensions 只是提示：模型无法输出该维度时按模型原生维度返回
+    pub async fn generate_local_embeddings(&self, texts: &[String], model: &str, dimensions: Option<usize>) -> Result<Vec<Vec<f32>>> {
+        let model = self.resolve_local_model(model).await?;
+        let native = model.dimensions as usize;
+        let dimensions = match dimensions {
+            Some(d) if d > native => {
+                println!("⚠️ 本地模型 {} 输出 {} 维，小于请求的维度，按模型维度返回", model.name, native);
+                None
+            }
+            Some(d) if d < native && !self.supports_reduced_dimensions(&model)? => {
+                println!("⚠️ 本地模型 {} 不支持降维输出，忽略请求的 {} 维，按模型的 {} 维返回", model.name, d, native);
+                None
+            }
+            other => other,
+        };
+
+        let context = EmbeddingContext::default().with_dimensions(dimensions);
+        self.generate_real_embeddings_batch(texts, &model, "", &context).await
+    }
+
+    // 查找本地嵌入模型
+    async fn resolve_local_model(&self, model: &str) -> Result<EmbeddingModel> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM embedding_models WHERE (id = ? OR model_id = ? OR name = ?) AND provider = ? AND enabled = 1 LIMIT 1",
+            EMBEDDING_MODEL_COLUMNS
+        ))
+        .bind(model)
+        .bind(model)
+        .bind(model)
+        .bind(LOCAL_PROVIDER_ID)
+        .fetch_optional(self.db.main_pool())
+        .await?;
+
+        if let Some(row) = row {
+            return Ok(embedding_model_from_row(&row));
+        }
+
+        // 未注册时允许直接传入模型目录
+        let info = local_embedding::inspect_model_dir(std::path::Path::new(model))
+            .map_err(|e| anyhow!("未找到本地嵌入模型 {}，请先注册本地模型: {}", model, e))?;
+        let mut local_model = EmbeddingModel::new(
+            model.to_string(),
+            model.to_string(),
+            info.dimensions as i32,
+            "default".to_string(),
+            info.max_tokens as i32,
+        );
+        local_model.provider = LOCAL_PROVIDER_ID.to_string();
+        Ok(local_model)
+    }
+
     // 调用实际的嵌入服务
     async fn generate_real_embedding(&self, text: &str, model: &EmbeddingModel, api_key: &str) -> Result<Vec<f32>> {
-        // 调用 SiliconFlow API 或其他嵌入服务
-        let result = crate::siliconflow_embedding::generate_siliconflow_embedding(
-            api_key.to_string(),
-            text.to_string(),
-            model.model_id.clone()
-        ).await;
+        // 单条文本同样走模型对应的提供方（结果已归一化）
+        let embeddings = self.generate_real_embeddings_batch(&[text.to_string()], model, api_key, &EmbeddingContext::default()).await?;
+        embeddings.into_iter().next().ok_or_else(|| anyhow!("嵌入提供方返回空结果"))
+    }
+
+    // 在限流器许可下调用提供方，校验返回数量并记录用量
+    async fn embed_with_provider(&self, provider: &dyn EmbeddingProvider, texts: &[String], model: &EmbeddingModel, api_key: &str, context: &EmbeddingContext) -> Result<Vec<Vec<f32>>> {
+        let limiter = self.limiter_for(provider);
+        let estimated_tokens = embedding_http::estimate_tokens(texts);
+        let _permit = limiter.acquire(estimated_tokens).await;
+
+        // 请求低于原生维度的输出只允许用于支持降维的模型（Matryoshka），其他模型截断前缀得到的向量没有意义
+        let requested = context.dimensions.filter(|d| *d < provider.dimensions(model));
+        if let Some(dimensions) = requested {
+            if !provider.supports_dimensions(model) {
+                return Err(anyhow!(
+                    "嵌入模型 {} 不支持降维输出，无法生成 {} 维向量",
+                    model.model_id, dimensions
+                ));
+            }
+        }
 
-        // 转换 Result<Vec<f32>, String> 为 Result<Vec<f32>, anyhow::Error>
-        let embedding = result.map_err(|e| anyhow::anyhow!(e))?;
-        
-        // 应用L2归一化
-        Ok(normalize_vector(embedding))
+        let started = std::time::Instant::now();
+        let EmbeddingBatch { mut embeddings, usage } = provider.embed_batch(model, texts, api_key, requested).await?;
+        self.record_usage(provider, model, texts.len(), usage, estimated_tokens, started.elapsed(), context).await;
+
+        if embeddings.len() != texts.len() {
+            return Err(anyhow!(
+                "嵌入提供方 {} 返回数量不匹配: 输入 {} 个，返回 {} 个",
+                provider.name(), texts.len(), embeddings.len()
+            ));
+        }
+
+        // 服务端返回的维度多于请求时取前缀（Matryoshka 模型的前缀即低维向量）
+        if let Some(dimensions) = requested {
+            for embedding in &mut embeddings {
+                if embedding.len() < dimensions {
+                    return Err(anyhow!(
+                        "嵌入提供方 {} 返回 {} 维向量，小于请求的 {} 维",
+                        provider.name(), embedding.len(), dimensions
+                    ));
+                }
+                embedding.truncate(dimensions);
+            }
+        }
+
+        Ok(embeddings)
     }
 
-    // 批量调用实际的嵌入服务
-    async fn generate_real_embeddings_batch(&self, texts: &[String], model: &EmbeddingModel, api_key: &str) -> Result<Vec<Vec<f32>>> {
-        // 硅基流动限制：batch 最大 32
-        let max_batch_size: usize = 32;
+    // 记录一次提供方调用的用量，提供方未返回用量时按估算值记录；写入失败不影响嵌入结果
+    #[allow(clippy::too_many_arguments)]
+    async fn record_usage(&self, provider: &dyn EmbeddingProvider, model: &EmbeddingModel, text_count: usize, usage: Option<TokenUsage>, estimated_tokens: usize, latency: std::time::Duration, context: &EmbeddingContext) {
+        let (usage, estimated) = match usage {
+            Some(usage) => (usage, false),
+            None => (TokenUsage { prompt_tokens: estimated_tokens as i64, total_tokens: estimated_tokens as i64 }, true),
+        };
+
+        let record = EmbeddingUsageRecord {
+            provider: provider.name().to_string(),
+            model_id: model.model_id.clone(),
+            operation: if context.operation.is_empty() { "other".to_string() } else { context.operation.clone() },
+            collection_id: context.collection_id.clone(),
+            document_id: context.document_id.clone(),
+            text_count: text_count as i64,
+            prompt_tokens: usage.prompt_tokens,
+            total_tokens: usage.total_tokens,
+            estimated,
+            latency_ms: latency.as_millis() as i64,
+            created_at: chrono::Utc::now(),
+        };
+
+        if let Err(e) = self.db.record_embedding_usage(&record).await {
+            println!("⚠️ 记录嵌入用量失败: {}", e);
+        }
+    }
+
+    // 生成一个子批次的嵌入：提供方因请求过大拒绝时，批次对半拆分重试；单条超长文本则拆成两段分别嵌入
+    #[allow(clippy::too_many_arguments)]
+    fn embed_adaptive<'a>(&'a self, job: &'a EmbeddingJobGuard<'a>, provider: &'a dyn EmbeddingProvider, texts: Vec<String>, model: &'a EmbeddingModel, api_key: &'a str, context: &'a EmbeddingContext, depth: usize) -> EmbedFuture<'a> {
+        Box::pin(async move {
+            // 限流和服务端故障的重试都在请求层（send_with_retry）完成
+            let error = match job.run(self.embed_with_provider(provider, &texts, model, api_key, context)).await {
+                Ok(embeddings) => {
+                    return Ok(texts.into_iter()
+                        .zip(embeddings)
+                        .map(|(text, embedding)| vec![EmbeddedText { text, embedding: normalize_vector(embedding) }])
+                        .collect());
+                }
+                Err(e) => e,
+            };
+
+            let too_large = error.downcast_ref::<EmbeddingError>().is_some_and(|e| e.is_payload_too_large());
+            if !too_large || depth >= MAX_SPLIT_DEPTH {
+                return Err(error);
+            }
+
+            if texts.len() > 1 {
+                let mut left = texts;
+                let right = left.split_off(left.len() / 2);
+                println!("✂️ 批次过大被拒绝，拆分为 {} + {} 个文本重试", left.len(), right.len());
+                let mut results = self.embed_adaptive(job, provider, left, model, api_key, context, depth + 1).await?;
+                results.extend(self.embed_adaptive(job, provider, right, model, api_key, context, depth + 1).await?);
+                return Ok(results);
+            }
+
+            // 单条文本超出模型输入限制：拆分后分别嵌入，而不是截断丢弃内容
+            let Some((first, second)) = split_text_near_middle(&texts[0]) else {
+                return Err(error);
+            };
+            println!("✂️ 单条文本超出模型输入限制（{} 字符），拆分为两段重新嵌入", texts[0].chars().count());
+            let pieces = self.embed_adaptive(job, provider, vec![first, second], model, api_key, context, depth + 1).await?;
+            Ok(vec![pieces.into_iter().flatten().collect()])
+        })
+    }
+
+    // 批量调用实际的嵌入服务，超长文本拆分出的多个片段向量取平均作为该文本的向量
+    async fn generate_real_embeddings_batch(&self, texts: &[String], model: &EmbeddingModel, api_key: &str, context: &EmbeddingContext) -> Result<Vec<Vec<f32>>> {
+        let results = self.generate_real_embeddings_split(texts, model, api_key, context).await?;
+        Ok(results.into_iter().map(merge_pieces).collect())
+    }
+
+    // 批量调用实际的嵌入服务，保留超长文本拆分后的各个片段（向量已归一化）
+    // 先查内存和磁盘上的嵌入缓存，只为未命中的文本调用提供方
+    async fn generate_real_embeddings_split(&self, texts: &[String], model: &EmbeddingModel, api_key: &str, context: &EmbeddingContext) -> Result<Vec<Vec<EmbeddedText>>> {
+        let model_key = embedding_cache_model_key(model, context.dimensions);
+        let hashes: Vec<String> = texts.iter().map(|t| text_hash(t)).collect();
+        let mut results: Vec<Option<Vec<EmbeddedText>>> = vec![None; texts.len()];
+
+        // 内存缓存
+        {
+            let mut cache = self.embedding_cache.write().await;
+            for (i, hash) in hashes.iter().enumerate() {
+                if let Some(entry) = cache.get(&format!("{}:{}", model_key, hash)) {
+                    results[i] = entry.to_pieces(&texts[i]);
+                }
+            }
+        }
+
+        // 磁盘缓存（embedding_cache 表），读取失败时退化为直接调用提供方
+        let missing_hashes: Vec<String> = hashes.iter().zip(&results)
+            .filter(|(_, r)| r.is_none())
+            .map(|(h, _)| h.clone())
+            .collect();
+        if !missing_hashes.is_empty() {
+            match self.db.get_cached_embeddings(&model_key, &missing_hashes).await {
+                Ok(cached) if !cached.is_empty() => {
+                    let mut cache = self.embedding_cache.write().await;
+                    for (i, hash) in hashes.iter().enumerate() {
+                        if results[i].is_some() {
+                            continue;
+                        }
+                        if let Some(entry) = cached.get(hash) {
+                            results[i] = entry.to_pieces(&texts[i]);
+                            if results[i].is_some() {
+                                cache.put(format!("{}:{}", model_key, hash), entry.clone());
+                            }
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => println!("⚠️ 读取嵌入缓存失败: {}", e),
+            }
+        }
+
+        let missing: Vec<usize> = (0..texts.len()).filter(|&i| results[i].is_none()).collect();
+        if missing.len() < texts.len() {
+            println!("💾 嵌入缓存命中 {}/{} 个文本", texts.len() - missing.len(), texts.len());
+        }
+
+        if !missing.is_empty() {
+            let missing_texts: Vec<String> = missing.iter().map(|&i| texts[i].clone()).collect();
+            let embedded = self.embed_uncached_split(&missing_texts, model, api_key, context).await?;
+
+            let mut entries = Vec::new();
+            {
+                let mut cache = self.embedding_cache.write().await;
+                for (&i, pieces) in missing.iter().zip(embedded) {
+                    // 被拆分的文本连同断点一起缓存，命中时还原出相同的片段
+                    let entry = CachedEmbedding::from_pieces(&pieces);
+                    cache.put(format!("{}:{}", model_key, hashes[i]), entry.clone());
+                    entries.push((hashes[i].clone(), entry));
+                    results[i] = Some(pieces);
+                }
+            }
+
+            if let Err(e) = self.db.put_cached_embeddings(&model_key, &entries).await {
+                println!("⚠️ 写入嵌入缓存失败: {}", e);
+            }
+        }
+
+        Ok(results.into_iter().map(|r| r.unwrap_or_default()).collect())
+    }
+
+    // 为未命中缓存的文本调用提供方
+    async fn embed_uncached_split(&self, texts: &[String], model: &EmbeddingModel, api_key: &str, context: &EmbeddingContext) -> Result<Vec<Vec<EmbeddedText>>> {
+        let provider = self.providers.get(&model.provider)?;
+        let max_batch_size = provider.max_batch().max(1);
+
+        // 登记任务，cancel_embedding_jobs 可中断正在进行的批次
+        let job = self.jobs.start();
 
         if texts.len() <= max_batch_size {
             // 如果数量在限制内，直接调用
-            let result = crate::siliconflow_embedding::generate_siliconflow_batch_embeddings(
-                api_key.to_string(),
-                texts.to_vec(),
-                model.model_id.clone()
-            ).await;
-            let embeddings = result.map_err(|e| anyhow::anyhow!(e))?;
-            
-            // 应用L2归一化到每个向量
-            let normalized_embeddings: Vec<Vec<f32>> = embeddings.into_iter()
-                .map(normalize_vector)
-                .collect();
-            
-            return Ok(normalized_embeddings);
+            return self.embed_adaptive(&job, provider.as_ref(), texts.to_vec(), model, api_key, context, 0).await;
         }
 
         // 分批发送请求
@@ -191,24 +602,20 @@ impl VectorService {
         let mut all_embeddings = Vec::new();
 
         for (batch_index, chunk) in texts.chunks(max_batch_size).enumerate() {
-            println!("🔄 处理第 {}/{} 批 ({} 个文本)", batch_index + 1, (texts.len() + max_batch_size - 1) / max_batch_size, chunk.len());
+            println!("🔄 处理第 {}/{} 批 ({} 个文本)", batch_index + 1, texts.len().div_ceil(max_batch_size), chunk.len());
 
-            let result = crate::siliconflow_embedding::generate_siliconflow_batch_embeddings(
-                api_key.to_string(),
-                chunk.to_vec(),
-                model.model_id.clone()
-            ).await;
+            let result = self.embed_adaptive(&job, provider.as_ref(), chunk.to_vec(), model, api_key, context, 0).await;
 
             match result {
                 Ok(batch_embeddings) => {
                     let count = batch_embeddings.len();
-                    // 应用L2归一化到每个向量
-                    let normalized_batch: Vec<Vec<f32>> = batch_embeddings.into_iter()
-                        .map(normalize_vector)
-                        .collect();
-                    all_embeddings.extend(normalized_batch);
+                    all_embeddings.extend(batch_embeddings);
                     println!("✅ 第 {} 批处理完成，获得 {} 个向量", batch_index + 1, count);
                 },
+                Err(e) if e.is::<EmbeddingCancelled>() => {
+                    println!("🛑 嵌入任务已取消，已完成 {} 个向量", all_embeddings.len());
+                    return Err(e);
+                },
                 Err(e) => {
                     println!("❌ 第 {} 批处理失败: {}", batch_index + 1, e);
                     return Err(anyhow::anyhow!("批量处理第 {} 批失败: {}", batch_index + 1, e));
@@ -511,6 +918,132 @@ impl VectorService {
         let embedding_cache = self.embedding_cache.read().await;
         let model_cache = self.model_cache.read().await;
 
-        (embedding_cache.len(), embedding_cache.cap().into(), model_cache.len())
+        (embedding_cache.entries.len(), embedding_cache.entries.cap().into(), model_cache.len())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // 测试用提供方：任一文本超过 max_chars 个字符时整批以 413 拒绝，向量为 [字符数, 1]
+    struct StubProvider {
+        max_chars: usize,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for StubProvider {
+        fn name(&self) -> &str {
+            "stub"
+        }
+
+        fn max_batch(&self) -> usize {
+            8
+        }
+
+        async fn embed_batch(&self, _model: &EmbeddingModel, texts: &[String], _api_key: &str, _dimensions: Option<usize>) -> Result<EmbeddingBatch> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if texts.iter().any(|t| t.chars().count() > self.max_chars) {
+                return Err(EmbeddingError::Http {
+                    provider: "stub".to_string(),
+                    status: 413,
+                    body: "payload too large".to_string(),
+                    retry_after: None,
+                }.into());
+            }
+            Ok(EmbeddingBatch {
+                embeddings: texts.iter().map(|t| vec![t.chars().count() as f32, 1.0]).collect(),
+                usage: None,
+            })
+        }
+    }
+
+    async fn stub_service(max_chars: usize) -> (VectorService, Arc<StubProvider>, EmbeddingModel) {
+        let db = Arc::new(DatabaseManager::in_memory().await.unwrap());
+        let service = VectorService::new(db);
+        let provider = Arc::new(StubProvider { max_chars, calls: AtomicUsize::new(0) });
+        service.register_provider(provider.clone());
+
+        let mut model = EmbeddingModel::new("Stub".to_string(), "stub-embed".to_string(), 2, "default".to_string(), 512);
+        model.provider = "stub".to_string();
+        (service, provider, model)
+    }
+
+    #[test]
+    fn splits_text_near_middle_at_natural_breaks() {
+        let (first, second) = split_text_near_middle("第一句话很短。第二句话也不长。第三句").unwrap();
+        assert_eq!((first.as_str(), second.as_str()), ("第一句话很短。", "第二句话也不长。第三句"));
+
+        let (first, second) = split_text_near_middle("alpha beta gamma delta").unwrap();
+        assert_eq!((first.as_str(), second.as_str()), ("alpha beta ", "gamma delta"));
+
+        // 没有标点和空白时从中间切开，不会切断多字节字符
+        let (first, second) = split_text_near_middle("内存安全保证").unwrap();
+        assert_eq!((first.as_str(), second.as_str()), ("内存安", "全保证"));
+
+        assert!(split_text_near_middle("字").is_none());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn bisects_batch_around_oversized_text() {
+        let (service, provider, model) = stub_service(8).await;
+        let texts: Vec<String> = ["alpha", "beta", "一段超出模型上限的很长的文本", "gamma"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+
+        let results = service
+            .generate_split_embeddings_with_api_key(&texts, &model, "", &EmbeddingContext::default())
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 4);
+        for (i, pieces) in results.iter().enumerate() {
+            assert_eq!(pieces.iter().map(|p| p.text.as_str()).collect::<String>(), texts[i]);
+            assert!(pieces.iter().all(|p| p.text.chars().count() <= 8));
+        }
+        assert_eq!(results[0].len(), 1);
+        assert!(results[2].len() > 1);
+        assert!(provider.calls.load(Ordering::SeqCst) > 1);
+
+        // 再次嵌入时全部命中缓存（包括拆分过的文本），不再调用提供方
+        let calls = provider.calls.load(Ordering::SeqCst);
+        service.clear_cache().await;
+        let cached = service
+            .generate_split_embeddings_with_api_key(&texts, &model, "", &EmbeddingContext::default())
+            .await
+            .unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), calls);
+        for (pieces, cached_pieces) in results.iter().zip(&cached) {
+            let texts: Vec<&str> = pieces.iter().map(|p| p.text.as_str()).collect();
+            let cached_texts: Vec<&str> = cached_pieces.iter().map(|p| p.text.as_str()).collect();
+            assert_eq!(texts, cached_texts);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_reduced_dimensions_for_unsupported_models() {
+        let (service, provider, model) = stub_service(100).await;
+        let context = EmbeddingContext::default().with_dimensions(Some(1));
+
+        let error = service.generate_query_embedding("text", &model, "", &context).await.unwrap_err();
+        assert!(error.to_string().contains("不支持降维输出"));
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 0);
+        assert!(!service.supports_reduced_dimensions(&model).unwrap());
+    }
+
+    #[test]
+    fn memory_cache_evicts_by_total_size() {
+        let entry = |value: f32| CachedEmbedding { embeddings: vec![vec![value; 4]], split_offsets: Vec::new() };
+        let mut cache = EmbeddingMemoryCache::new(100, 10);
+        cache.put("a".to_string(), entry(1.0));
+        cache.put("b".to_string(), entry(2.0));
+        cache.put("c".to_string(), entry(3.0));
+
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some() && cache.get("c").is_some());
+        assert_eq!(cache.floats, 8);
+    }
+}